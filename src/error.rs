@@ -0,0 +1,196 @@
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+
+use crate::util::DEFAULT_OUI_PATHS;
+use crate::{PingBackend, ResolverKind, Target};
+
+/// Errors returned by the library.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The system `ping` command is needed but was not found in `$PATH`.
+    PingNotFound,
+    /// Raw sockets are needed but this process is not allowed to open them.
+    ///
+    /// On Linux this needs root or `CAP_NET_RAW`.
+    RawSocketPermission(io::Error),
+    /// ICMP datagram sockets are needed but this process is not allowed to
+    /// open them.
+    ///
+    /// On Linux this needs a group within `net.ipv4.ping_group_range`.
+    DatagramSocketPermission(io::Error),
+    /// Raw packet sockets are needed for ARP discovery but this process is
+    /// not allowed to open them.
+    ///
+    /// On Linux this needs root or `CAP_NET_RAW`.
+    PacketSocketPermission(io::Error),
+    /// None of the ICMP backends tried, listed best first, can be used.
+    NoUsableBackend(Vec<PingBackend>),
+    /// The requested interface does not exist.
+    InterfaceNotFound(String),
+    /// The network interfaces could not be listed.
+    InterfaceEnumeration(io::Error),
+    /// A hostname target could not be resolved.
+    TargetResolution { target: String, source: io::Error },
+    /// A target covers more hosts than [`ScanOptions::max_subnet_hosts`](crate::ScanOptions::max_subnet_hosts).
+    TooManyHosts {
+        target: Target,
+        hosts: u128,
+        limit: usize,
+    },
+    /// A target of an ARP sweep is not on the subnet of any local interface.
+    NotOnLocalSubnet(IpAddr),
+    /// Vendor lookup was requested without an OUI file, and none is installed.
+    OuiDatabaseNotFound,
+    /// The requested hostname resolver is not available.
+    ///
+    /// The system resolver needs `avahi-resolve` on Linux, the multicast DNS
+    /// resolver needs a usable interface, and the DNS resolver needs servers
+    /// from the options or `/etc/resolv.conf`.
+    ResolverUnavailable(ResolverKind),
+    /// The requested probe is not supported on this platform.
+    Unsupported(&'static str),
+    /// Any other I/O failure, with a description of what was being done.
+    Io { context: String, source: io::Error },
+}
+
+impl Error {
+    /// Wrap an I/O error with a description of what was being done.
+    pub(crate) fn io(context: impl Into<String>, source: io::Error) -> Self {
+        Error::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Like [`Error::io`], but a permission error becomes `denied`, the
+    /// variant for the kind of socket that was being used.
+    pub(crate) fn socket(
+        denied: fn(io::Error) -> Self,
+        context: impl Into<String>,
+        source: io::Error,
+    ) -> Self {
+        match source.kind() {
+            io::ErrorKind::PermissionDenied => denied(source),
+            _ => Error::io(context, source),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::PingNotFound => f.write_str("system `ping` command not found"),
+            Error::RawSocketPermission(e) => {
+                write!(f, "not permitted to open raw sockets: {}", e)
+            }
+            Error::DatagramSocketPermission(e) => {
+                write!(f, "not permitted to open ICMP datagram sockets: {}", e)
+            }
+            Error::PacketSocketPermission(e) => {
+                write!(f, "not permitted to open raw packet sockets: {}", e)
+            }
+            Error::NoUsableBackend(tried) => {
+                f.write_str("no usable ping backend: ")?;
+                for (i, backend) in tried.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str("; ")?;
+                    }
+                    f.write_str(match backend {
+                        PingBackend::System => "the system `ping` command was not found",
+                        PingBackend::RawSocket => "raw ICMP sockets cannot be opened",
+                        PingBackend::Datagram => "ICMP datagram sockets cannot be opened",
+                        PingBackend::Arp => "raw packet sockets cannot be opened",
+                        PingBackend::Tcp | PingBackend::Udp => "not usable",
+                    })?;
+                }
+                Ok(())
+            }
+            Error::InterfaceNotFound(interface) => {
+                write!(f, "interface '{}' not found", interface)
+            }
+            Error::InterfaceEnumeration(e) => {
+                write!(f, "failed to list network interfaces: {}", e)
+            }
+            Error::TargetResolution { target, source } => {
+                write!(f, "failed to resolve target '{}': {}", target, source)
+            }
+            Error::TooManyHosts {
+                target,
+                hosts,
+                limit,
+            } => write!(
+                f,
+                "target {} covers {} hosts, more than the limit of {}",
+                target, hosts, limit
+            ),
+            Error::NotOnLocalSubnet(ip_addr) => {
+                write!(f, "target {} is not on a local IPv4 subnet", ip_addr)
+            }
+            Error::OuiDatabaseNotFound => write!(
+                f,
+                "no OUI database found, looked in: {}",
+                DEFAULT_OUI_PATHS.join(", ")
+            ),
+            Error::ResolverUnavailable(ResolverKind::System) if cfg!(target_os = "linux") => {
+                f.write_str("system resolver needs `avahi-resolve`, which was not found")
+            }
+            Error::ResolverUnavailable(ResolverKind::System) => {
+                f.write_str("system hostname resolution is not supported on this platform")
+            }
+            Error::ResolverUnavailable(ResolverKind::Mdns) => {
+                f.write_str("no interface to send multicast DNS queries on")
+            }
+            Error::ResolverUnavailable(ResolverKind::Dns) => {
+                f.write_str("no DNS servers given and none found in /etc/resolv.conf")
+            }
+            Error::ResolverUnavailable(kind) => write!(f, "{} resolver is not available", kind),
+            Error::Unsupported(what) => write!(f, "{} is not supported on this platform", what),
+            Error::Io { context, source } => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::RawSocketPermission(e)
+            | Error::DatagramSocketPermission(e)
+            | Error::PacketSocketPermission(e)
+            | Error::InterfaceEnumeration(e)
+            | Error::TargetResolution { source: e, .. }
+            | Error::Io { source: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::Error;
+
+    #[test]
+    fn permission_errors_are_told_apart_from_other_io_errors() {
+        let denied = Error::socket(
+            Error::PacketSocketPermission,
+            "failed to open ARP socket on eth0",
+            io::Error::from(io::ErrorKind::PermissionDenied),
+        );
+        assert!(matches!(denied, Error::PacketSocketPermission(_)));
+
+        let other = Error::socket(
+            Error::PacketSocketPermission,
+            "failed to open ARP socket on eth0",
+            io::Error::from(io::ErrorKind::NotFound),
+        );
+        assert!(matches!(other, Error::Io { .. }));
+        assert!(
+            other
+                .to_string()
+                .starts_with("failed to open ARP socket on eth0: ")
+        );
+    }
+}
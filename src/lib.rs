@@ -4,19 +4,39 @@
 //! mirrors that tool's scan operation without exposing the lower-level probing
 //! implementation details.
 
-use std::collections::{BTreeMap, BTreeSet};
-use std::net::{IpAddr, Ipv6Addr};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use tokio::sync::Semaphore;
 use tokio::task::JoinSet;
 
+mod error;
+mod mac;
+mod resolve;
+mod result;
+mod target;
 mod util;
+mod watch;
 
+pub use error::Error;
+pub use mac::{MacAddr, ParseMacAddrError};
+pub use resolve::{
+    ConfirmFuture, CustomResolver, ResolveFuture, ResolveMode, Resolver, ResolverKind,
+};
+pub use result::{Hostname, PingStats, Probe, ScanResult, Service};
+pub use target::{ParseTargetError, Target};
+pub use util::{BackendSelection, Capabilities, PingBackend, TOP_TCP_PORTS};
+pub use watch::{WatchEvent, Watcher};
+
+use resolve::ResolverChain;
+use target::{ipv4_subnet_contains, local_ipv4_subnet};
 use util::{
-    DiscoveredAddress, InterfaceAddress, PingBackend, get_addresses, hostname_resolution_supported,
-    resolve_hostname, select_ping_backend, socket_ipv6_multicast_ping, socket_ping,
-    system_ipv6_multicast_ping, system_ping,
+    ArpSocket, HostnameResolver, IcmpEngine, InterfaceAddress, NeighbourTable, OuiDatabase, Reply,
+    Throttle, ThrottlePermit, browse, discover, get_addresses, open_ports,
+    socket_ipv6_multicast_ping, system_ipv6_multicast_ping, system_ping, tcp_ping, tcp_ping_ports,
 };
 
 /// Options for a local network scan.
@@ -24,36 +44,204 @@ use util::{
 pub struct ScanOptions {
     /// Interface to search. When unset, all non-loopback interfaces are scanned.
     pub interface: Option<String>,
+    /// Explicit targets to scan instead of the local subnets.
+    ///
+    /// When set, only these targets are probed and IPv6 multicast discovery is
+    /// skipped.
+    pub targets: Vec<Target>,
     /// Attempt to resolve hostnames for responding addresses.
     pub resolve_hostnames: bool,
-    /// Open raw sockets instead of using the system `ping` command where supported.
+    /// Built-in resolvers asked for hostnames, in order. When empty, Windows
+    /// uses the system resolver, and other platforms try
+    /// [`ResolverKind::Hosts`], [`ResolverKind::Mdns`],
+    /// [`ResolverKind::Netbios`] and then [`ResolverKind::Dns`], so a query is
+    /// sent to UDP port 137 of every host found that has no multicast DNS
+    /// name. Before 2.2.0, Linux used only [`ResolverKind::System`] by default.
+    ///
+    /// Multicast DNS and NetBIOS answers are awaited for up to
+    /// [`ScanOptions::ping_timeout`].
+    pub resolvers: Vec<ResolverKind>,
+    /// Additional resolvers, asked before the built-in ones.
+    pub custom_resolvers: Vec<CustomResolver>,
+    /// Whether to stop at the first resolver that finds a name, or ask them all.
+    pub resolve_mode: ResolveMode,
+    /// Look each name back up with the resolver that found it and record in
+    /// [`Hostname::confirmed`] whether it still leads to the host.
+    pub confirm_names: bool,
+    /// DNS servers queried by [`ResolverKind::Dns`]. When empty, the servers
+    /// in `/etc/resolv.conf` are used.
+    ///
+    /// Setting this without [`ScanOptions::resolvers`] selects the DNS resolver.
+    pub dns_servers: Vec<SocketAddr>,
+    /// Files read by [`ResolverKind::Hosts`] after `/etc/hosts` and the DHCP
+    /// lease files of dnsmasq, ISC dhcpd and systemd-networkd.
+    ///
+    /// Each may be in hosts format or any of those lease formats.
+    pub hosts_files: Vec<PathBuf>,
+    /// Look up the hardware address of each host in the kernel neighbour table.
+    ///
+    /// Only supported on Linux. Hosts outside the local subnets have no entry.
+    pub lookup_mac: bool,
+    /// Look up the vendor of each hardware address in an OUI database.
+    pub lookup_vendors: bool,
+    /// OUI database used for vendor lookups, in IEEE `oui.txt` or Wireshark
+    /// `manuf` format. When unset, a copy installed by the system is used.
+    pub oui_file: Option<PathBuf>,
+    /// Use raw sockets, failing if they cannot be opened, instead of picking
+    /// a backend automatically as described by [`BackendSelection`].
     pub raw_socket: bool,
-    /// Timeout of pings in seconds.
+    /// Backend for the unicast sweep. When unset, one is picked automatically.
+    ///
+    /// [`PingBackend::Arp`] only reaches IPv4 hosts on a local subnet; IPv6
+    /// targets and multicast discovery still use the automatically picked backend.
+    /// [`PingBackend::Tcp`] probes every unicast target, but multicast
+    /// discovery still uses ICMP.
+    pub method: Option<PingBackend>,
+    /// Ports tried by [`PingBackend::Tcp`]. When empty, 22, 80, 443 and 445
+    /// are tried.
+    pub tcp_ports: Vec<u16>,
+    /// TCP ports to try on each host found, such as [`TOP_TCP_PORTS`]. Those
+    /// that accept a connection are listed in [`ScanResult::open_ports`].
+    ///
+    /// Each connection waits up to [`ScanOptions::ping_timeout`] and counts
+    /// towards the scan's concurrency limit. When empty, no ports are checked.
+    ///
+    /// With [`PingBackend::Tcp`], ports the sweep already tried are not
+    /// connected to again: the sweep then waits for all of its ports instead
+    /// of the first answer, and reports those that accepted.
+    pub ports: Vec<u16>,
+    /// Also look for devices that only answer service discovery: send an SSDP
+    /// search, a multicast DNS query for `_services._dns-sd._udp.local` and,
+    /// on IPv4, a NetBIOS broadcast on each interface.
+    ///
+    /// Hosts found this way are reported with [`Probe::Ssdp`], [`Probe::Mdns`]
+    /// or [`Probe::Netbios`] unless the sweep found them first. Skipped when
+    /// [`ScanOptions::targets`] is set.
+    pub udp_discovery: bool,
+    /// Browse the DNS-SD services advertised over multicast DNS on each
+    /// interface and list them in [`ScanResult::services`].
+    ///
+    /// Browsing takes up to three rounds of [`ScanOptions::ping_timeout`]
+    /// alongside the sweep, and no result is passed to the callback of
+    /// [`scan_each_result`] until it has finished. Skipped when
+    /// [`ScanOptions::targets`] is set.
+    pub browse_services: bool,
+    /// Timeout of pings in whole seconds. When 0, [`ScanOptions::ping_timeout`]
+    /// is used instead.
+    #[deprecated(since = "2.2.0", note = "use `ping_timeout`, which takes a `Duration`")]
     pub timeout: usize,
+    /// How long to wait for each answer: echo replies, TCP connections,
+    /// discovery responses and multicast name lookups.
+    pub ping_timeout: Duration,
+    /// Number of echo requests sent to each target.
+    ///
+    /// Hosts that answer at least one of them are reported, with loss and
+    /// round-trip statistics in [`ScanResult::stats`].
+    pub count: usize,
+    /// Delay between successive echo requests to the same target.
+    pub interval: Duration,
     /// Scan IPv4 addresses.
     pub ipv4: bool,
     /// Scan IPv6 addresses.
     pub ipv6: bool,
+    /// Maximum number of probes in flight at once: `ping` processes, raw
+    /// socket echo requests and TCP connection attempts. 0 counts as 1.
+    pub concurrency: usize,
+    /// Maximum number of hosts whose names are looked up at once.
+    pub resolve_concurrency: usize,
+    /// Maximum number of probes started per second, including ARP requests.
+    /// When unset, only [`ScanOptions::concurrency`] limits the pace.
+    pub rate: Option<u32>,
+    /// Back off when probes can't be sent, such as when `ping` fails to start
+    /// or the send buffer is full, or when hosts that answered start missing
+    /// most of their echo requests.
+    ///
+    /// Each sign of strain halves the number of probes in flight, and the
+    /// rate with it, down to one; probes that could not be sent are retried.
+    /// The limits grow back gradually while probes go through.
+    pub adaptive: bool,
+    /// Maximum number of hosts to sweep on a single subnet or target.
+    ///
+    /// Larger local subnets are narrowed to the biggest subnet around the local
+    /// address that stays within this limit. Larger explicit targets are rejected.
+    pub max_subnet_hosts: usize,
 }
 
 impl Default for ScanOptions {
+    #[allow(deprecated)]
     fn default() -> Self {
         Self {
             interface: None,
+            targets: Vec::new(),
             resolve_hostnames: true,
+            resolvers: Vec::new(),
+            custom_resolvers: Vec::new(),
+            resolve_mode: ResolveMode::First,
+            confirm_names: false,
+            dns_servers: Vec::new(),
+            hosts_files: Vec::new(),
+            lookup_mac: true,
+            lookup_vendors: false,
+            oui_file: None,
             raw_socket: false,
-            timeout: 1,
+            method: None,
+            tcp_ports: Vec::new(),
+            ports: Vec::new(),
+            udp_discovery: false,
+            browse_services: false,
+            timeout: 0,
+            ping_timeout: Duration::from_secs(1),
+            count: 1,
+            interval: Duration::from_secs(1),
             ipv4: true,
             ipv6: true,
+            concurrency: 150,
+            resolve_concurrency: 150,
+            rate: None,
+            adaptive: false,
+            max_subnet_hosts: 1024,
         }
     }
 }
 
+impl ScanOptions {
+    /// The timeout a scan uses: the deprecated [`ScanOptions::timeout`] if it
+    /// is set, and [`ScanOptions::ping_timeout`] otherwise.
+    #[allow(deprecated)]
+    pub fn effective_timeout(&self) -> Duration {
+        match self.timeout {
+            0 => self.ping_timeout,
+            seconds => Duration::from_secs(seconds as u64),
+        }
+    }
+}
+
+/// List the interfaces a scan with these options would search.
+///
+/// Explicit targets are not tied to an interface, so this is empty when
+/// [`ScanOptions::targets`] is set.
+pub fn scan_interfaces(options: &ScanOptions) -> Result<Vec<String>, Error> {
+    if !options.targets.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let interfaces: BTreeSet<_> = get_addresses(options.interface.clone())?
+        .into_iter()
+        .filter_map(|address| match address {
+            InterfaceAddress::V4 { interface, .. } if options.ipv4 => Some(interface),
+            InterfaceAddress::V6 { interface, .. } if options.ipv6 => Some(interface),
+            _ => None,
+        })
+        .collect();
+
+    Ok(interfaces.into_iter().collect())
+}
+
 /// Scan the local network and return the lines normally printed by the CLI.
 ///
 /// Results are deduplicated and formatted as either `IP` or `IP<TAB>hostname`,
 /// depending on whether hostname resolution is requested and succeeds.
-pub async fn scan(options: ScanOptions) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+pub async fn scan(options: ScanOptions) -> Result<Vec<String>, Error> {
     let mut results = Vec::new();
     scan_each(options, |result| results.push(result)).await?;
     Ok(results)
@@ -63,59 +251,242 @@ pub async fn scan(options: ScanOptions) -> Result<Vec<String>, Box<dyn std::erro
 ///
 /// Results are deduplicated before they are passed to the callback. The callback
 /// receives the same formatted lines returned by [`scan`].
-pub async fn scan_each<F>(
-    options: ScanOptions,
-    mut on_result: F,
-) -> Result<(), Box<dyn std::error::Error>>
+pub async fn scan_each<F>(options: ScanOptions, mut on_result: F) -> Result<(), Error>
 where
     F: FnMut(String),
 {
-    let resolve = options.resolve_hostnames && hostname_resolution_supported();
-    let system_ping_exists = util::command_exists("ping");
+    scan_each_result(options, |result| on_result(result.to_string())).await
+}
 
-    let ping_backend = select_ping_backend(options.raw_socket, system_ping_exists)?;
-    let addresses = get_addresses(options.interface);
-    let semaphore = Arc::new(Semaphore::new(150));
+/// Scan the local network and return a structured result for each responding host.
+pub async fn scan_results(options: ScanOptions) -> Result<Vec<ScanResult>, Error> {
+    let mut results = Vec::new();
+    scan_each_result(options, |result| results.push(result)).await?;
+    Ok(results)
+}
+
+/// Scan the local network and call `on_result` as each host is found.
+///
+/// Results are deduplicated by [`ScanResult::display_addr`] before they are
+/// passed to the callback.
+///
+/// An interface whose IPv6 multicast ping or ARP sweep fails does not stop
+/// the others. The first such error is returned only if no host was found.
+pub async fn scan_each_result<F>(options: ScanOptions, mut on_result: F) -> Result<(), Error>
+where
+    F: FnMut(ScanResult),
+{
+    let mut resolvers = Vec::new();
+    if options.resolve_hostnames {
+        resolvers.extend(
+            options
+                .custom_resolvers
+                .iter()
+                .map(|resolver| Arc::clone(resolver)),
+        );
+        for resolver in HostnameResolver::select(&options)? {
+            resolvers.push(Arc::new(resolver) as Arc<dyn Resolver>);
+        }
+    }
+    let timeout = options.effective_timeout();
+    let details = Arc::new(HostDetails {
+        resolvers: ResolverChain {
+            confirm: options.confirm_names,
+            resolvers,
+            mode: options.resolve_mode,
+        },
+        ports: options.ports.clone(),
+        port_timeout: timeout,
+        resolve_limit: Semaphore::new(options.resolve_concurrency.max(1)),
+    });
+    let selection = BackendSelection::new(&options);
+    let system_ping_exists = selection.capabilities().system_ping;
+    // Without a working backend, the sweep fails below if it needs one.
+    let ping_backend = selection.preferred();
+    let arp = options.method == Some(PingBackend::Arp);
+    let tcp = options.method == Some(PingBackend::Tcp);
+    let vendors = if options.lookup_vendors {
+        Some(OuiDatabase::load(options.oui_file.as_deref())?)
+    } else {
+        None
+    };
+    let throttle = Arc::new(Throttle::new(
+        options.concurrency,
+        options.rate,
+        options.adaptive,
+    ));
 
     let mut tasks = JoinSet::new();
-    let mut ipv6_tasks = JoinSet::new();
+    let mut discovery_tasks = JoinSet::new();
     let mut ipv6_interfaces = BTreeMap::new();
-    for address in addresses {
-        match address {
-            InterfaceAddress::V4(address) if options.ipv4 => {
-                run_ipv4_subnet(
-                    &mut tasks,
-                    address,
-                    resolve,
-                    ping_backend,
-                    options.timeout,
-                    semaphore.clone(),
-                );
-            }
-            InterfaceAddress::V4(_) => {}
-            InterfaceAddress::V6 {
-                ip,
-                interface,
-                index,
-            } if options.ipv6 => {
-                let source = ipv6_interfaces.entry((interface, index)).or_insert(ip);
-                if ipv6_source_preferred(*source, ip) {
-                    *source = ip;
+    let mut sweep_targets = Vec::new();
+    let mut link_addresses = Vec::new();
+    // Explicit ARP targets go out of the interface whose subnet holds them.
+    let local_subnets = if arp && !options.targets.is_empty() {
+        local_ipv4_subnets(options.interface.clone())?
+    } else {
+        Vec::new()
+    };
+    if options.targets.is_empty() {
+        for address in get_addresses(options.interface.clone())? {
+            match address {
+                InterfaceAddress::V4 {
+                    ip,
+                    prefix_len,
+                    interface,
+                } if options.ipv4 => {
+                    if options.udp_discovery || options.browse_services {
+                        link_addresses.push(InterfaceAddress::V4 {
+                            ip,
+                            prefix_len,
+                            interface: interface.clone(),
+                        });
+                    }
+                    sweep_targets.push(SweepTarget {
+                        target: local_ipv4_subnet(ip, prefix_len, options.max_subnet_hosts),
+                        source: Some(IpAddr::V4(ip)),
+                        interface: Some(interface),
+                    });
+                }
+                InterfaceAddress::V4 { .. } => {}
+                InterfaceAddress::V6 {
+                    ip,
+                    interface,
+                    index,
+                } if options.ipv6 => {
+                    let source = ipv6_interfaces.entry((interface, index)).or_insert(ip);
+                    if ipv6_source_preferred(*source, ip) {
+                        *source = ip;
+                    }
                 }
+                InterfaceAddress::V6 { .. } => {}
             }
-            InterfaceAddress::V6 { .. } => {}
         }
+    } else {
+        sweep_targets.extend(options.targets.into_iter().map(|target| SweepTarget {
+            target,
+            source: None,
+            interface: None,
+        }));
+    }
+
+    let sweep_config = SweepConfig {
+        ping_backend: if tcp { PingBackend::Tcp } else { ping_backend },
+        tcp_ports: options.tcp_ports.into(),
+        tcp_list_open: !options.ports.is_empty(),
+        timeout,
+        count: options.count.max(1),
+        interval: options.interval,
+        retries: if options.adaptive { PROBE_RETRIES } else { 0 },
+        icmp: Arc::new(IcmpEngine::new(if ping_backend == PingBackend::Datagram {
+            socket2::Type::DGRAM
+        } else {
+            socket2::Type::RAW
+        })),
+    };
+    let sweep_addresses = expand_targets(
+        sweep_targets,
+        options.max_subnet_hosts,
+        options.ipv4,
+        options.ipv6,
+    )
+    .await?;
+    let icmp_needed = !ipv6_interfaces.is_empty()
+        || sweep_addresses
+            .iter()
+            .any(|address| !tcp && (!arp || address.ip_addr.is_ipv6()));
+    if icmp_needed {
+        selection.backend()?;
+    }
+
+    let mut arp_targets: BTreeMap<(String, Ipv4Addr), Vec<Ipv4Addr>> = BTreeMap::new();
+    for address in sweep_addresses {
+        let IpAddr::V4(ip_addr) = address.ip_addr else {
+            tasks.spawn(ping_address(
+                address,
+                sweep_config.clone(),
+                details.clone(),
+                throttle.clone(),
+            ));
+            continue;
+        };
+        if !arp {
+            tasks.spawn(ping_address(
+                address,
+                sweep_config.clone(),
+                details.clone(),
+                throttle.clone(),
+            ));
+            continue;
+        }
+
+        let local = match (address.interface, address.source) {
+            (Some(interface), Some(IpAddr::V4(source))) => (interface, source),
+            _ => local_subnets
+                .iter()
+                .find(|(_, source, prefix_len)| ipv4_subnet_contains(*source, *prefix_len, ip_addr))
+                .map(|(interface, source, _)| (interface.clone(), *source))
+                .ok_or(Error::NotOnLocalSubnet(address.ip_addr))?,
+        };
+        arp_targets.entry(local).or_default().push(ip_addr);
+    }
+
+    // Failures of single interfaces, reported if nothing at all is found.
+    let mut interface_error = None;
+    for ((interface, source), targets) in arp_targets {
+        let socket = match ArpSocket::open(&interface, source) {
+            Ok(socket) => socket,
+            Err(e) => {
+                interface_error.get_or_insert(match e.kind() {
+                    std::io::ErrorKind::Unsupported => Error::Unsupported("ARP discovery"),
+                    _ => Error::socket(
+                        Error::PacketSocketPermission,
+                        format!("failed to open ARP socket on {}", interface),
+                        e,
+                    ),
+                });
+                continue;
+            }
+        };
+        discovery_tasks.spawn(collect_arp_interface(
+            socket,
+            interface,
+            targets,
+            sweep_config.clone(),
+            throttle.clone(),
+        ));
     }
 
     let ipv6_config = Ipv6ScanConfig {
-        resolve_hostnames: resolve,
         ping_backend,
         system_ping_exists,
-        timeout: options.timeout,
+        timeout,
+        count: options.count.max(1),
+        interval: options.interval,
     };
 
+    if options.udp_discovery || options.browse_services {
+        link_addresses.extend(ipv6_interfaces.iter().map(|((interface, index), ip)| {
+            InterfaceAddress::V6 {
+                ip: *ip,
+                interface: interface.clone(),
+                index: *index,
+            }
+        }));
+    }
+    let mut service_tasks = JoinSet::new();
+    for address in link_addresses {
+        if options.browse_services {
+            let address = address.clone();
+            service_tasks.spawn(async move { browse(&address, timeout).await });
+        }
+        if options.udp_discovery {
+            discovery_tasks.spawn(collect_udp_discovery(address, timeout));
+        }
+    }
+
     for ((interface, index), source) in ipv6_interfaces {
-        ipv6_tasks.spawn(collect_ipv6_interface(
+        discovery_tasks.spawn(collect_ipv6_interface(
             interface,
             index,
             source,
@@ -123,62 +494,184 @@ where
         ));
     }
 
-    while let Some(result) = ipv6_tasks.join_next().await {
-        let Ok(addresses) = result else {
-            continue;
-        };
+    while let Some(result) = discovery_tasks.join_next().await {
+        match result {
+            Ok(Ok(results)) => {
+                for result in results {
+                    tasks.spawn(complete_result(result, details.clone(), throttle.clone()));
+                }
+            }
+            Ok(Err(e)) => {
+                interface_error.get_or_insert(e);
+            }
+            Err(_) => {}
+        }
+    }
 
-        for address in addresses {
-            tasks.spawn(format_successful_address(
-                address,
-                ipv6_config.resolve_hostnames,
-                semaphore.clone(),
-            ));
+    // Results only carry their services once browsing is done, so streaming
+    // waits for it when it was asked for.
+    let mut services: HashMap<IpAddr, Vec<Service>> = HashMap::new();
+    if options.browse_services {
+        while let Some(browsed) = service_tasks.join_next().await {
+            for (ip_addr, service) in browsed.unwrap_or_default() {
+                let known = services.entry(ip_addr).or_default();
+                if !known.contains(&service) {
+                    known.push(service);
+                }
+            }
         }
     }
 
     let mut seen = BTreeSet::new();
+    let mut neighbours = NeighbourTable::default();
     while let Some(result) = tasks.join_next().await {
-        if let Ok(Some(result)) = result
-            && seen.insert(result.clone())
+        if let Ok(Some(mut result)) = result
+            && seen.insert(result.display_addr())
         {
+            if options.lookup_mac && result.mac.is_none() {
+                result.mac = neighbours.lookup_or_refresh(result.ip_addr, result.scope.as_deref());
+            }
+            if let (Some(vendors), Some(mac)) = (&vendors, result.mac) {
+                result.vendor = vendors.lookup(mac).map(str::to_string);
+            }
+            result.services = services.get(&result.ip_addr).cloned().unwrap_or_default();
             on_result(result);
         }
     }
 
-    Ok(())
+    match interface_error {
+        Some(e) if seen.is_empty() => Err(e),
+        _ => Ok(()),
+    }
 }
 
-/// Ping all the IP addresses on the local IPv4 `/24`.
-fn run_ipv4_subnet(
-    tasks: &mut JoinSet<Option<String>>,
-    address: std::net::Ipv4Addr,
-    resolve_hostnames: bool,
-    ping_backend: PingBackend,
-    timeout: usize,
-    semaphore: Arc<Semaphore>,
-) {
-    let octets = address.octets();
-
-    for i in 1..255 {
-        let ip_addr = IpAddr::V4(std::net::Ipv4Addr::new(octets[0], octets[1], octets[2], i));
-        tasks.spawn(ping_address(
-            ip_addr,
-            Some(IpAddr::V4(address)),
-            resolve_hostnames,
-            ping_backend,
-            timeout,
-            semaphore.clone(),
-        ));
+/// Scan repeatedly, calling `on_event` whenever a host comes up, goes down or changes.
+///
+/// A new round starts every `every`, or as soon as the previous round
+/// finishes if it took longer. A host is reported down after missing `grace`
+/// rounds in a row. This only returns if a scan fails.
+pub async fn watch<F>(
+    options: ScanOptions,
+    every: Duration,
+    grace: usize,
+    mut on_event: F,
+) -> Result<(), Error>
+where
+    F: FnMut(WatchEvent),
+{
+    let mut watcher = Watcher::new(grace);
+    loop {
+        let round_start = tokio::time::Instant::now();
+        scan_each_result(options.clone(), |result| {
+            if let Some(event) = watcher.observe(result) {
+                on_event(event);
+            }
+        })
+        .await?;
+        for event in watcher.end_round() {
+            on_event(event);
+        }
+
+        tokio::time::sleep_until(round_start + every).await;
     }
 }
 
+/// A target to sweep, with the local address and interface it is reached from.
+struct SweepTarget {
+    target: Target,
+    source: Option<IpAddr>,
+    interface: Option<String>,
+}
+
+/// A single address to probe during the sweep.
+struct SweepAddress {
+    ip_addr: IpAddr,
+    source: Option<IpAddr>,
+    interface: Option<String>,
+}
+
+/// Expand sweep targets into the unicast addresses to probe.
+///
+/// Hostnames are resolved here, and addresses outside the requested address
+/// families or already listed by an earlier target are dropped.
+async fn expand_targets(
+    targets: Vec<SweepTarget>,
+    max_hosts: usize,
+    ipv4: bool,
+    ipv6: bool,
+) -> Result<Vec<SweepAddress>, Error> {
+    let mut seen = BTreeSet::new();
+    let mut addresses = Vec::new();
+
+    for SweepTarget {
+        target,
+        source,
+        interface,
+    } in targets
+    {
+        let target_addresses: Vec<IpAddr> = match &target {
+            Target::Host(host) => tokio::net::lookup_host((host.as_str(), 0))
+                .await
+                .map_err(|source| Error::TargetResolution {
+                    target: host.clone(),
+                    source,
+                })?
+                .map(|address| address.ip())
+                .collect(),
+            _ => {
+                let host_count = target.host_count().unwrap_or(0);
+                if host_count > max_hosts as u128 {
+                    return Err(Error::TooManyHosts {
+                        target,
+                        hosts: host_count,
+                        limit: max_hosts,
+                    });
+                }
+                target.addresses().collect()
+            }
+        };
+
+        for ip_addr in target_addresses {
+            let family_enabled = if ip_addr.is_ipv4() { ipv4 } else { ipv6 };
+            if family_enabled && seen.insert(ip_addr) {
+                addresses.push(SweepAddress {
+                    ip_addr,
+                    source,
+                    interface: interface.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(addresses)
+}
+
+#[derive(Clone)]
+struct SweepConfig {
+    ping_backend: PingBackend,
+    tcp_ports: Arc<[u16]>,
+    /// Whether TCP probes wait for every port to list the open ones, which
+    /// then need no separate check.
+    tcp_list_open: bool,
+    timeout: Duration,
+    count: usize,
+    interval: Duration,
+    /// Times a probe that could not be sent is tried again.
+    retries: usize,
+    /// Shared by every raw socket probe of the sweep.
+    icmp: Arc<IcmpEngine>,
+}
+
+/// Retries of probes that could not be sent, in adaptive mode.
+const PROBE_RETRIES: usize = 3;
+
 #[derive(Clone, Copy)]
 struct Ipv6ScanConfig {
-    resolve_hostnames: bool,
     ping_backend: PingBackend,
     system_ping_exists: bool,
-    timeout: usize,
+    timeout: Duration,
+    count: usize,
+    interval: Duration,
 }
 
 async fn collect_ipv6_interface(
@@ -186,48 +679,224 @@ async fn collect_ipv6_interface(
     index: Option<u32>,
     source: Ipv6Addr,
     config: Ipv6ScanConfig,
-) -> Vec<DiscoveredAddress> {
-    match socket_ipv6_multicast_ping(
-        &interface,
-        index,
-        source,
-        config.timeout,
-        config.ping_backend,
-    )
-    .await
-    {
-        Ok(addresses) => addresses,
-        Err(()) if config.system_ping_exists => {
-            system_ipv6_multicast_ping(&interface, index, config.timeout).await
+) -> Result<Vec<ScanResult>, Error> {
+    let mut backend = config.ping_backend;
+    let mut responders: BTreeMap<(IpAddr, Option<String>), (usize, Vec<Duration>)> =
+        BTreeMap::new();
+
+    for sequence in 0..config.count {
+        if sequence > 0 {
+            tokio::time::sleep(config.interval).await;
+        }
+
+        let addresses = match socket_ipv6_multicast_ping(
+            &interface,
+            index,
+            source,
+            config.timeout,
+            config.ping_backend,
+            sequence as u16,
+        )
+        .await
+        {
+            Ok(addresses) => addresses,
+            Err(_) if config.system_ping_exists => {
+                backend = PingBackend::System;
+                system_ipv6_multicast_ping(&interface, index, config.timeout).await
+            }
+            Err(e) => return Err(e),
+        };
+
+        for address in addresses {
+            let (received, rtts) = responders
+                .entry((address.ip_addr, address.scope))
+                .or_default();
+            *received += 1;
+            rtts.extend(address.rtt);
         }
-        Err(()) => Vec::new(),
     }
+
+    Ok(responders
+        .into_iter()
+        .map(|((ip_addr, scope), (received, rtts))| {
+            let stats = PingStats::new(config.count, received, &rtts);
+            let mut result = ScanResult::new(ip_addr, Probe::Ipv6Multicast, backend);
+            result.scope = scope;
+            result.rtt = stats.avg;
+            result.stats = Some(stats);
+            result
+        })
+        .collect())
+}
+
+async fn collect_udp_discovery(
+    address: InterfaceAddress,
+    timeout: Duration,
+) -> Result<Vec<ScanResult>, Error> {
+    Ok(discover(&address, timeout)
+        .await
+        .into_iter()
+        .map(|(probe, address)| {
+            let mut result = ScanResult::new(address.ip_addr, probe, PingBackend::Udp);
+            result.scope = address.scope;
+            result.rtt = address.rtt;
+            result
+        })
+        .collect())
+}
+
+/// Local IPv4 addresses with the interface they are on and their prefix length.
+fn local_ipv4_subnets(interface: Option<String>) -> Result<Vec<(String, Ipv4Addr, u8)>, Error> {
+    Ok(get_addresses(interface)?
+        .into_iter()
+        .filter_map(|address| match address {
+            InterfaceAddress::V4 {
+                ip,
+                prefix_len,
+                interface,
+            } => Some((interface, ip, prefix_len)),
+            InterfaceAddress::V6 { .. } => None,
+        })
+        .collect())
+}
+
+async fn collect_arp_interface(
+    socket: ArpSocket,
+    interface: String,
+    targets: Vec<Ipv4Addr>,
+    config: SweepConfig,
+    throttle: Arc<Throttle>,
+) -> Result<Vec<ScanResult>, Error> {
+    let responders = socket
+        .sweep(
+            &targets,
+            config.timeout,
+            config.count,
+            config.interval,
+            &throttle,
+        )
+        .await
+        .map_err(|e| {
+            Error::socket(
+                Error::PacketSocketPermission,
+                format!("ARP sweep on {} failed", interface),
+                e,
+            )
+        })?;
+
+    Ok(responders
+        .into_iter()
+        .map(|(ip_addr, responder)| {
+            let stats = PingStats::new(config.count, responder.received, &responder.rtts);
+            let mut result = ScanResult::new(IpAddr::V4(ip_addr), Probe::Sweep, PingBackend::Arp);
+            result.scope = Some(interface.clone());
+            result.mac = Some(responder.mac);
+            result.rtt = stats.avg;
+            result.stats = Some(stats);
+            result
+        })
+        .collect())
 }
 
 async fn ping_address(
-    ip_addr: IpAddr,
-    source: Option<IpAddr>,
-    resolve_hostnames: bool,
-    ping_backend: PingBackend,
-    timeout: usize,
-    semaphore: Arc<Semaphore>,
-) -> Option<String> {
-    let _permit = match semaphore.acquire().await {
-        Ok(permit) => permit,
-        Err(_) => return None,
-    };
+    address: SweepAddress,
+    config: SweepConfig,
+    details: Arc<HostDetails>,
+    throttle: Arc<Throttle>,
+) -> Option<ScanResult> {
+    let mut received = 0;
+    let mut rtts = Vec::new();
+    let mut open_ports = Vec::new();
+    for sequence in 0..config.count {
+        if sequence > 0 {
+            tokio::time::sleep(config.interval).await;
+        }
 
-    let success = match ping_backend {
-        PingBackend::RawSocket => socket_ping(&ip_addr, source, timeout).await,
-        PingBackend::System => system_ping(&ip_addr, timeout).await,
+        for attempt in 0..=config.retries {
+            let permit = throttle.acquire().await?;
+            match send_probe(&address, &config, permit).await {
+                Ok(reply) => {
+                    throttle.succeeded();
+                    if let Some(reply) = reply {
+                        received += 1;
+                        rtts.extend(reply.rtt);
+                        open_ports.extend(reply.open_ports);
+                    }
+                    break;
+                }
+                Err(_) => {
+                    throttle.failed();
+                    if attempt == config.retries {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    if received == 0 {
+        return None;
+    }
+    // A host that answered but then missed most of its requests suggests
+    // probes or replies are being dropped along the way.
+    if received * 2 < config.count {
+        throttle.failed();
+    }
+
+    let stats = PingStats::new(config.count, received, &rtts);
+    let mut result = ScanResult::new(address.ip_addr, Probe::Sweep, config.ping_backend);
+    result.scope = address.interface;
+    result.rtt = stats.avg;
+    result.stats = Some(stats);
+    // TCP probes already connected to their ports; only check the others.
+    let probed = match config.ping_backend {
+        PingBackend::Tcp => tcp_ping_ports(&config.tcp_ports),
+        _ => &[],
     };
+    open_ports.retain(|port| details.ports.contains(port));
+    open_ports.sort_unstable();
+    open_ports.dedup();
+    result.open_ports = open_ports;
+    add_details(&mut result, address.source, probed, &details, &throttle).await?;
+
+    Some(result)
+}
 
-    match (success, resolve_hostnames) {
-        (true, true) => resolve_hostname(&ip_addr)
+/// Send one probe to `address` with the sweep's backend.
+///
+/// ICMP socket probes give `permit` back as soon as the request is sent, so
+/// the sweep can keep sending while replies are awaited. Other probes hold it
+/// until they finish.
+///
+/// Fails if the probe could not be sent; no answer is `Ok(None)`.
+async fn send_probe(
+    address: &SweepAddress,
+    config: &SweepConfig,
+    permit: ThrottlePermit,
+) -> std::io::Result<Option<Reply>> {
+    match config.ping_backend {
+        PingBackend::RawSocket | PingBackend::Datagram => {
+            let echo = config.icmp.send(address.ip_addr, address.source).await;
+            drop(permit);
+            match echo? {
+                Some(echo) => echo.reply(config.timeout).await,
+                None => Ok(None),
+            }
+        }
+        PingBackend::System => system_ping(&address.ip_addr, config.timeout).await,
+        PingBackend::Tcp => {
+            tcp_ping(
+                &address.ip_addr,
+                address.source,
+                &config.tcp_ports,
+                config.timeout,
+                config.tcp_list_open,
+            )
             .await
-            .or_else(|| Some(ip_addr.to_string())),
-        (true, false) => Some(ip_addr.to_string()),
-        _ => None,
+        }
+        // ARP sweeps run per interface in `collect_arp_interface`, and
+        // UDP discovery in `collect_udp_discovery`.
+        PingBackend::Arp | PingBackend::Udp => Ok(None),
     }
 }
 
@@ -235,27 +904,96 @@ fn ipv6_source_preferred(current: Ipv6Addr, candidate: Ipv6Addr) -> bool {
     !current.is_unicast_link_local() && candidate.is_unicast_link_local()
 }
 
-async fn format_successful_address(
-    address: DiscoveredAddress,
-    resolve_hostnames: bool,
-    semaphore: Arc<Semaphore>,
-) -> Option<String> {
-    let _permit = match semaphore.acquire().await {
-        Ok(permit) => permit,
-        Err(_) => return None,
-    };
+async fn complete_result(
+    mut result: ScanResult,
+    details: Arc<HostDetails>,
+    throttle: Arc<Throttle>,
+) -> Option<ScanResult> {
+    add_details(&mut result, None, &[], &details, &throttle).await?;
 
-    if resolve_hostnames {
-        resolve_hostname(&address.ip_addr)
-            .await
-            .or(Some(address.display_addr))
-    } else {
-        Some(address.display_addr)
+    Some(result)
+}
+
+/// What to find out about each host once it has answered.
+struct HostDetails {
+    resolvers: ResolverChain,
+    ports: Vec<u16>,
+    port_timeout: Duration,
+    /// Bounds how many hosts are resolved at once.
+    resolve_limit: Semaphore,
+}
+
+/// Check the open ports of a host that answered, then resolve its names.
+///
+/// Ports in `probed` were already connected to by the sweep, which filled in
+/// those that are open.
+///
+/// Returns `None` if the scan's limits were closed.
+async fn add_details(
+    result: &mut ScanResult,
+    source: Option<IpAddr>,
+    probed: &[u16],
+    details: &HostDetails,
+    throttle: &Arc<Throttle>,
+) -> Option<()> {
+    let ports: Vec<u16> = details
+        .ports
+        .iter()
+        .copied()
+        .filter(|port| !probed.contains(port))
+        .collect();
+    if !ports.is_empty() {
+        result.open_ports.extend(
+            open_ports(
+                host_socket_addr(result),
+                source,
+                &ports,
+                details.port_timeout,
+                throttle,
+            )
+            .await,
+        );
+        result.open_ports.sort_unstable();
+    }
+
+    if !details.resolvers.is_empty() {
+        let _permit = details.resolve_limit.acquire().await.ok()?;
+        result.hostnames = details
+            .resolvers
+            .resolve(result.ip_addr, result.scope.as_deref())
+            .await;
+        result.hostname = result
+            .hostnames
+            .first()
+            .map(|hostname| hostname.name.clone());
+    }
+
+    Some(())
+}
+
+/// The address of a result's host, with the interface index a link-local
+/// IPv6 address needs to be connected to.
+fn host_socket_addr(result: &ScanResult) -> SocketAddr {
+    match (result.ip_addr, &result.scope) {
+        (IpAddr::V6(ip_addr), Some(scope)) if ip_addr.is_unicast_link_local() => {
+            let index = scope.parse().ok().or_else(|| {
+                get_addresses(Some(scope.clone()))
+                    .ok()?
+                    .into_iter()
+                    .find_map(|address| match address {
+                        InterfaceAddress::V6 { index, .. } => index,
+                        InterfaceAddress::V4 { .. } => None,
+                    })
+            });
+            SocketAddr::V6(SocketAddrV6::new(ip_addr, 0, 0, index.unwrap_or(0)))
+        }
+        (ip_addr, _) => SocketAddr::new(ip_addr, 0),
     }
 }
 
 #[doc(hidden)]
 pub mod cli_support {
+    #[allow(deprecated)]
     pub use super::util::{
         PingBackend, can_open_raw_socket, command_exists, hostname_resolution_supported,
         raw_socket_supported, select_ping_backend,
@@ -264,7 +1002,59 @@ pub mod cli_support {
 
 #[cfg(test)]
 mod tests {
-    use super::ipv6_source_preferred;
+    use std::net::IpAddr;
+    use std::time::Duration;
+
+    use super::{CustomResolver, ResolveFuture, Resolver, ScanOptions, ipv6_source_preferred};
+
+    #[test]
+    #[allow(deprecated)]
+    fn seconds_timeouts_still_take_precedence_when_set() {
+        let mut options = ScanOptions {
+            ping_timeout: Duration::from_millis(250),
+            ..ScanOptions::default()
+        };
+        assert_eq!(options.effective_timeout(), Duration::from_millis(250));
+
+        options.timeout = 2;
+        assert_eq!(options.effective_timeout(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn options_compare_custom_resolvers_by_instance() {
+        let options = ScanOptions {
+            custom_resolvers: vec![CustomResolver::new(Named)],
+            ..ScanOptions::default()
+        };
+
+        assert_eq!(options, options.clone());
+        assert_ne!(
+            options,
+            ScanOptions {
+                custom_resolvers: vec![CustomResolver::new(Named)],
+                ..ScanOptions::default()
+            }
+        );
+        assert_ne!(
+            options,
+            ScanOptions {
+                count: 3,
+                ..options.clone()
+            }
+        );
+    }
+
+    struct Named;
+
+    impl Resolver for Named {
+        fn name(&self) -> &str {
+            "named"
+        }
+
+        fn resolve<'a>(&'a self, _ip_addr: IpAddr, _scope: Option<&'a str>) -> ResolveFuture<'a> {
+            Box::pin(async { Vec::new() })
+        }
+    }
 
     #[test]
     fn ipv6_source_selection_prefers_link_local_for_multicast() {
@@ -0,0 +1,85 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A 48-bit Ethernet hardware address.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct MacAddr([u8; 6]);
+
+impl MacAddr {
+    /// Create a hardware address from its six octets.
+    pub const fn new(octets: [u8; 6]) -> Self {
+        Self(octets)
+    }
+
+    /// The six octets of the address.
+    pub const fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+}
+
+/// Error returned when a string is not a valid [`MacAddr`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseMacAddrError(String);
+
+impl fmt::Display for ParseMacAddrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid MAC address '{}'", self.0)
+    }
+}
+
+impl std::error::Error for ParseMacAddrError {}
+
+/// Parses `aa:bb:cc:dd:ee:ff` or `aa-bb-cc-dd-ee-ff`, in either case.
+impl FromStr for MacAddr {
+    type Err = ParseMacAddrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseMacAddrError(s.to_string());
+        let mut octets = [0; 6];
+        let mut parts = s.split([':', '-']);
+        for octet in &mut octets {
+            let part = parts
+                .next()
+                .filter(|part| part.len() == 2)
+                .ok_or_else(error)?;
+            *octet = u8::from_str_radix(part, 16).map_err(|_| error())?;
+        }
+        if parts.next().is_some() {
+            return Err(error());
+        }
+
+        Ok(Self(octets))
+    }
+}
+
+/// Formats the address as lowercase, colon-separated hex, e.g. `b8:27:eb:12:34:56`.
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            a, b, c, d, e, g
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MacAddr;
+
+    #[test]
+    fn mac_addresses_round_trip_through_strings() {
+        let mac: MacAddr = "B8-27-EB-12-34-56".parse().unwrap();
+
+        assert_eq!(mac, MacAddr::new([0xb8, 0x27, 0xeb, 0x12, 0x34, 0x56]));
+        assert_eq!(mac.to_string(), "b8:27:eb:12:34:56");
+    }
+
+    #[test]
+    fn mac_addresses_reject_malformed_input() {
+        assert!("b8:27:eb:12:34".parse::<MacAddr>().is_err());
+        assert!("b8:27:eb:12:34:56:78".parse::<MacAddr>().is_err());
+        assert!("b8:27:eb:12:34:5g".parse::<MacAddr>().is_err());
+    }
+}
@@ -1,24 +1,80 @@
-use std::io::{IsTerminal, stderr, stdout};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
 
-use pingall::cli_support::{
-    PingBackend, can_open_raw_socket, command_exists, hostname_resolution_supported,
-    raw_socket_supported, select_ping_backend,
+use pingall::cli_support::PingBackend;
+use pingall::{
+    BackendSelection, Error, ResolveMode, ResolverKind, ScanOptions, TOP_TCP_PORTS, Target,
+    scan_each_result, scan_interfaces, watch,
 };
-use pingall::{ScanOptions, scan_each};
+
+mod output;
+
+use output::{Format, PlainColumns, Printer, ScanMetadata};
 
 const HELP: &str = "\
 Ping everything you can reach.
 
-Usage: pingall [OPTIONS]
+Usage: pingall [OPTIONS] [TARGETS]...
+
+Arguments:
+  [TARGETS]...  Networks, ranges, addresses or hostnames to scan instead of the local subnets,
+                e.g. 10.20.0.0/20, 192.168.5.10-40, 192.168.5.7 or host.example
 
 Options:
   -i, --interface <INTERFACE>  Interface to search
+      --targets-file <PATH>    Read targets from a file, one or more per line; `#` starts a comment
   -d, --dont-resolve           Don't attempt to resolve hostnames
       --no-resolve             Alias for --dont-resolve
+      --resolve <RESOLVERS>    Comma-separated hostname resolvers to try in order: hosts, mdns, netbios,
+                               dns or system. `hosts` reads /etc/hosts and DHCP lease files, `mdns` asks
+                               hosts directly over multicast DNS, `netbios` asks Windows and Samba hosts
+                               for WORKGROUP\\NAME, `dns` asks DNS servers, `system` uses avahi-resolve
+                               on Linux, which was the default before 2.2.0 [default: auto,
+                               hosts,mdns,netbios,dns]
+      --resolve-mode <MODE>    Stop at the first resolver that finds a name, or ask all of them and
+                               show every name: first or all [default: first]
+      --confirm-names          Look each name back up and mark those that no longer lead to the host
+                               as (unconfirmed)
+      --dns-server <ADDR>      DNS server for the dns resolver, e.g. 192.168.1.1 or [fd00::1]:5353.
+                               May be repeated [default: servers in /etc/resolv.conf]
+      --hosts-file <PATH>      Extra hosts or DHCP lease file for the hosts resolver. May be repeated
   -r, --raw-socket             Open raw socket instead of using system `ping` command. Unix only, requires permissions
-  -t, --timeout <TIMEOUT>      Timeout of pings in seconds [default: 1]
+  -m, --method <METHOD>        Probe method: auto, system, raw, datagram, arp or tcp. `datagram` uses
+                               unprivileged ICMP sockets where allowed. `arp` finds hosts that ignore
+                               ping on local IPv4 subnets and shows their MAC address. Linux only,
+                               requires permissions. `tcp` connects to --ports instead [default: auto]
+      --explain-backend        Print which probe mechanisms work and why the method was chosen
+      --ports <PORTS>          Comma-separated TCP ports, or top100, to check on each host found. Ports
+                               that accept a connection are shown as open. With --method tcp, a host is
+                               up when any of them accepts or refuses the connection [default: none, or
+                               22,80,443,445 for --method tcp]
+      --udp-discovery          Also send SSDP, mDNS and NetBIOS discovery queries on each interface to
+                               find devices that only answer those, such as TVs and printers
+      --services               Browse the DNS-SD services each host advertises over mDNS and list them
+                               below it; hosts are printed once browsing has finished
+  -t, --timeout <TIMEOUT>      Time to wait for each answer, e.g. 250ms or 1.5s; plain numbers are seconds
+                               [default: 1s]
+  -c, --count <COUNT>          Number of echo requests to send to each target [default: 1]
+      --interval <INTERVAL>    Delay between echo requests to a target, e.g. 200ms or 1.5s [default: 1s]
   -4, --ipv4                   Scan IPv4 addresses only
   -6, --ipv6                   Scan IPv6 addresses only
+      --max-hosts <MAX_HOSTS>  Maximum hosts to sweep per IPv4 subnet [default: 1024]
+      --concurrency <N>        Maximum probes in flight at once: ping processes, echo requests and TCP
+                               connections [default: 150]
+      --resolve-concurrency <N>
+                               Maximum hosts whose names are looked up at once [default: 150]
+      --rate <PPS>             Maximum probes started per second [default: unlimited]
+      --adaptive               Slow down when probes fail to send or hosts start dropping replies
+  -w, --watch                  Keep scanning and report hosts that come up, go down or change
+      --every <INTERVAL>       Delay between the starts of watch rounds, e.g. 30s or 5m [default: 30s]
+      --grace <ROUNDS>         Rounds a host may miss before it is reported down [default: 2]
+  -f, --format <FORMAT>        Output format: plain, tsv, csv, json or ndjson [default: plain]
+      --show-rtt               Show the round-trip time of each host in plain output
+      --show-mac               Show the MAC address of each host in plain output
+      --vendor                 Look up the vendor of each MAC address and show it in plain output
+      --oui-file <PATH>        OUI database for --vendor, in IEEE oui.txt or Wireshark manuf format
+                               [default: the copy installed by the system]
   -h, --help                   Print help
   -V, --version                Print version
 ";
@@ -26,11 +82,37 @@ Options:
 #[derive(Debug)]
 struct Args {
     interface: Option<String>,
+    targets: Vec<Target>,
     dont_resolve: bool,
+    resolvers: Vec<ResolverKind>,
+    resolve_mode: ResolveMode,
+    confirm_names: bool,
+    dns_servers: Vec<SocketAddr>,
+    hosts_files: Vec<PathBuf>,
     raw_socket: bool,
-    timeout: usize,
+    method: Option<PingBackend>,
+    explain_backend: bool,
+    ports: Vec<u16>,
+    udp_discovery: bool,
+    services: bool,
+    timeout: Duration,
+    count: usize,
+    interval: Duration,
     ipv4: bool,
     ipv6: bool,
+    max_hosts: usize,
+    concurrency: usize,
+    resolve_concurrency: usize,
+    rate: Option<u32>,
+    adaptive: bool,
+    watch: bool,
+    every: Duration,
+    grace: usize,
+    format: Format,
+    show_rtt: bool,
+    show_mac: bool,
+    vendor: bool,
+    oui_file: Option<PathBuf>,
 }
 
 impl Args {
@@ -58,33 +140,238 @@ impl Args {
         let dont_resolve = args.contains(["-d", "--dont-resolve"]);
         let no_resolve = args.contains("--no-resolve");
 
-        let parsed = Self {
+        let targets_file: Option<String> = args.opt_value_from_str("--targets-file")?;
+
+        let mut parsed = Self {
             interface: args.opt_value_from_str(["-i", "--interface"])?,
+            targets: Vec::new(),
             dont_resolve: dont_resolve || no_resolve,
+            resolvers: args
+                .opt_value_from_fn("--resolve", parse_resolvers)?
+                .unwrap_or_default(),
+            resolve_mode: args
+                .opt_value_from_str("--resolve-mode")?
+                .unwrap_or_default(),
+            confirm_names: args.contains("--confirm-names"),
+            dns_servers: args.values_from_fn("--dns-server", parse_dns_server)?,
+            hosts_files: args.values_from_str("--hosts-file")?,
             raw_socket: args.contains(["-r", "--raw-socket"]),
-            timeout: args.opt_value_from_str(["-t", "--timeout"])?.unwrap_or(1),
+            method: args
+                .opt_value_from_fn(["-m", "--method"], parse_method)?
+                .flatten(),
+            explain_backend: args.contains("--explain-backend"),
+            ports: args
+                .opt_value_from_fn("--ports", parse_ports)?
+                .unwrap_or_default(),
+            udp_discovery: args.contains("--udp-discovery"),
+            services: args.contains("--services"),
+            timeout: args
+                .opt_value_from_fn(["-t", "--timeout"], parse_duration)?
+                .unwrap_or(Duration::from_secs(1)),
+            count: args.opt_value_from_str(["-c", "--count"])?.unwrap_or(1),
+            interval: args
+                .opt_value_from_fn("--interval", parse_duration)?
+                .unwrap_or(Duration::from_secs(1)),
             ipv4: args.contains(["-4", "--ipv4"]),
             ipv6: args.contains(["-6", "--ipv6"]),
+            max_hosts: args.opt_value_from_str("--max-hosts")?.unwrap_or(1024),
+            concurrency: args.opt_value_from_str("--concurrency")?.unwrap_or(150),
+            resolve_concurrency: args
+                .opt_value_from_str("--resolve-concurrency")?
+                .unwrap_or(150),
+            rate: args.opt_value_from_str("--rate")?,
+            adaptive: args.contains("--adaptive"),
+            watch: args.contains(["-w", "--watch"]),
+            every: args
+                .opt_value_from_fn("--every", parse_duration)?
+                .unwrap_or(Duration::from_secs(30)),
+            grace: args.opt_value_from_str("--grace")?.unwrap_or(2),
+            format: args
+                .opt_value_from_str(["-f", "--format"])?
+                .unwrap_or(Format::Plain),
+            show_rtt: args.contains("--show-rtt"),
+            show_mac: args.contains("--show-mac"),
+            vendor: args.contains("--vendor"),
+            oui_file: args.opt_value_from_str("--oui-file")?,
         };
 
+        if parsed.timeout.is_zero() {
+            return Err("the argument '--timeout' must be greater than 0".into());
+        }
+
+        if parsed.count == 0 {
+            return Err("the argument '--count' must be at least 1".into());
+        }
+
+        if parsed.grace == 0 {
+            return Err("the argument '--grace' must be at least 1".into());
+        }
+
+        if parsed.concurrency == 0 {
+            return Err("the argument '--concurrency' must be at least 1".into());
+        }
+
+        if parsed.resolve_concurrency == 0 {
+            return Err("the argument '--resolve-concurrency' must be at least 1".into());
+        }
+
+        if parsed.rate == Some(0) {
+            return Err("the argument '--rate' must be at least 1".into());
+        }
+
         if parsed.ipv4 && parsed.ipv6 {
             return Err("the argument '--ipv4' cannot be used with '--ipv6'".into());
         }
 
-        let remaining = args.finish();
-        if !remaining.is_empty() {
-            let unexpected = remaining
-                .into_iter()
-                .map(|arg| arg.to_string_lossy().into_owned())
-                .collect::<Vec<_>>()
-                .join(" ");
-            return Err(format!("unexpected argument: {}", unexpected).into());
+        if let Some(path) = targets_file {
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| format!("failed to read targets file '{}': {}", path, e))?;
+            parsed.targets.extend(parse_targets_file(&contents)?);
+        }
+
+        let remaining = args
+            .finish()
+            .into_iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        let unexpected = remaining
+            .iter()
+            .filter(|arg| arg.starts_with('-'))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !unexpected.is_empty() {
+            return Err(format!("unexpected argument: {}", unexpected.join(" ")).into());
+        }
+        for target in remaining {
+            parsed.targets.push(target.parse()?);
         }
 
         Ok(parsed)
     }
 }
 
+/// Parse a `--method` value. `auto` leaves the choice to the library.
+fn parse_method(value: &str) -> Result<Option<PingBackend>, String> {
+    match value {
+        "auto" => Ok(None),
+        value => value.parse().map(Some).map_err(|_| {
+            format!(
+                "unknown method '{}', expected one of: auto, system, raw, datagram, arp, tcp",
+                value
+            )
+        }),
+    }
+}
+
+/// Parse a `--resolve` list. `auto` leaves the choice to the library.
+fn parse_resolvers(value: &str) -> Result<Vec<ResolverKind>, String> {
+    if value == "auto" {
+        return Ok(Vec::new());
+    }
+
+    value
+        .split(',')
+        .map(|resolver| {
+            resolver.trim().parse().map_err(|_| {
+                format!(
+                    "unknown resolver '{}', expected auto or a list of: hosts, mdns, netbios, dns, system",
+                    resolver
+                )
+            })
+        })
+        .collect()
+}
+
+/// Parse a `--dns-server` value: an address with an optional port, 53 by default.
+fn parse_dns_server(value: &str) -> Result<SocketAddr, String> {
+    value
+        .parse()
+        .or_else(|_| value.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+        .map_err(|_| {
+            format!(
+                "invalid DNS server '{}', expected e.g. 192.168.1.1 or [fd00::1]:53",
+                value
+            )
+        })
+}
+
+/// Parse a comma-separated `--ports` list, or `top100`.
+fn parse_ports(value: &str) -> Result<Vec<u16>, String> {
+    if value == "top100" {
+        return Ok(TOP_TCP_PORTS.to_vec());
+    }
+    value
+        .split(',')
+        .map(|port| match port.trim().parse() {
+            Ok(0) | Err(_) => Err(format!(
+                "invalid port '{}', expected top100 or numbers from 1 to 65535",
+                port
+            )),
+            Ok(port) => Ok(port),
+        })
+        .collect()
+}
+
+/// Parse a duration such as `250ms`, `1.5s` or `2m`. Bare numbers are seconds.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, scale) = if let Some(number) = value.strip_suffix("ms") {
+        (number, 0.001)
+    } else if let Some(number) = value.strip_suffix('s') {
+        (number, 1.0)
+    } else if let Some(number) = value.strip_suffix('m') {
+        (number, 60.0)
+    } else if let Some(number) = value.strip_suffix('h') {
+        (number, 3600.0)
+    } else {
+        (value, 1.0)
+    };
+
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .and_then(|number| Duration::try_from_secs_f64(number * scale).ok())
+        .ok_or_else(|| {
+            format!(
+                "invalid duration '{}', expected e.g. 250ms, 1.5s or 2m",
+                value
+            )
+        })
+}
+
+/// Parse a targets file with whitespace-separated targets and `#` comments.
+fn parse_targets_file(contents: &str) -> Result<Vec<Target>, pingall::ParseTargetError> {
+    contents
+        .lines()
+        .map(|line| line.split_once('#').map_or(line, |(line, _)| line))
+        .flat_map(str::split_whitespace)
+        .map(str::parse)
+        .collect()
+}
+
+/// Describe the capabilities probed and the backend chosen from them.
+fn explain_backend(selection: &BackendSelection) -> String {
+    let capabilities = selection.capabilities();
+    let yes_no = |available: bool| if available { "yes" } else { "no" };
+    let chain: Vec<String> = selection.chain().iter().map(ToString::to_string).collect();
+    format!(
+        "raw ICMP sockets:       {}\n\
+         ICMP datagram sockets:  {}\n\
+         system ping command:    {}\n\
+         raw packet sockets:     {}\n\
+         backends, best first:   {}\n\
+         chosen:                 {}\n",
+        yes_no(capabilities.raw_icmp),
+        yes_no(capabilities.datagram_icmp),
+        yes_no(capabilities.system_ping),
+        yes_no(capabilities.raw_packet),
+        chain.join(", "),
+        selection
+            .chosen()
+            .map_or_else(|| "none".to_string(), |backend| backend.to_string()),
+    )
+}
+
 fn main() {
     tokio::runtime::Builder::new_current_thread()
         .enable_all()
@@ -93,6 +380,26 @@ fn main() {
         .block_on(async {
             if let Err(e) = run().await {
                 eprintln!("Error: {}", e);
+                if let Some(Error::RawSocketPermission(_)) = e.downcast_ref() {
+                    eprintln!(
+                        "Either run as root, or run `setcap cap_net_raw+ep $(which pingall)` to allow this app to open raw sockets."
+                    );
+                }
+                if let Some(Error::PacketSocketPermission(_)) = e.downcast_ref() {
+                    eprintln!(
+                        "ARP discovery needs root, or run `setcap cap_net_raw+ep $(which pingall)` to allow this app to open packet sockets."
+                    );
+                }
+                if let Some(Error::NoUsableBackend(_)) = e.downcast_ref() {
+                    eprintln!(
+                        "Install `ping`, run `setcap cap_net_raw+ep $(which pingall)`, or allow your group with `sysctl net.ipv4.ping_group_range`. --method tcp needs none of these."
+                    );
+                }
+                if let Some(Error::DatagramSocketPermission(_)) = e.downcast_ref() {
+                    eprintln!(
+                        "Allow your group with `sysctl net.ipv4.ping_group_range`, or use --method raw or system."
+                    );
+                }
                 std::process::exit(1);
             }
         })
@@ -101,46 +408,146 @@ fn main() {
 async fn run() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse()?;
 
-    let resolve_hostnames = if args.dont_resolve {
-        false
-    } else if hostname_resolution_supported() {
-        true
-    } else {
-        if cfg!(target_os = "linux") && stdout().is_terminal() {
-            eprintln!("`avahi-resolve` not found, hostname resolution disabled");
-        }
-        false
-    };
-
-    if args.raw_socket && !raw_socket_supported() && stderr().is_terminal() {
-        eprintln!(
-            "Raw socket mode is unsupported on this platform; falling back to system `ping`."
-        );
-    }
-
-    let system_ping_exists = command_exists("ping");
-    let ping_backend = select_ping_backend(args.raw_socket, system_ping_exists)?;
-    if args.scan_ipv4()
-        && ping_backend == PingBackend::RawSocket
-        && !can_open_raw_socket().await
-        && stderr().is_terminal()
-    {
-        let err_msg = "Either run as root, or run `setcap cap_net_raw+ep $(which pingall)` to allow this app to open raw sockets.";
-        eprintln!("Error opening raw socket.\n{}", err_msg);
-    }
-
+    let resolve_hostnames = !args.dont_resolve;
     let ipv4 = args.scan_ipv4();
     let ipv6 = args.scan_ipv6();
     let options = ScanOptions {
         interface: args.interface,
+        targets: args.targets,
         resolve_hostnames,
+        resolvers: args.resolvers,
+        custom_resolvers: Vec::new(),
+        resolve_mode: args.resolve_mode,
+        confirm_names: args.confirm_names,
+        dns_servers: args.dns_servers,
+        hosts_files: args.hosts_files,
+        lookup_mac: true,
+        lookup_vendors: args.vendor || args.oui_file.is_some(),
+        oui_file: args.oui_file,
         raw_socket: args.raw_socket,
-        timeout: args.timeout,
+        method: args.method,
+        tcp_ports: args.ports.clone(),
+        ports: args.ports,
+        udp_discovery: args.udp_discovery,
+        browse_services: args.services,
+        ping_timeout: args.timeout,
+        count: args.count,
+        interval: args.interval,
         ipv4,
         ipv6,
+        concurrency: args.concurrency,
+        resolve_concurrency: args.resolve_concurrency,
+        rate: args.rate,
+        adaptive: args.adaptive,
+        max_subnet_hosts: args.max_hosts,
+        ..ScanOptions::default()
+    };
+    if args.explain_backend {
+        eprint!("{}", explain_backend(&BackendSelection::new(&options)));
+    }
+
+    let columns = PlainColumns {
+        mac: args.show_mac || args.method == Some(PingBackend::Arp),
+        vendor: options.lookup_vendors,
+        rtt: args.show_rtt,
+        stats: args.count > 1,
+        all_names: options.resolve_mode == ResolveMode::All,
+        open_ports: !options.ports.is_empty(),
+        services: options.browse_services,
     };
+    if args.watch {
+        let mut printer = Printer::for_events(args.format, columns);
+        watch(options, args.every, args.grace, |event| {
+            printer.print_event(&event)
+        })
+        .await?;
+        return Ok(());
+    }
 
-    scan_each(options, |result| println!("{}", result)).await?;
+    let interfaces = scan_interfaces(&options)?;
+    let started_at = SystemTime::now();
+    let start = Instant::now();
+    let mut printer = Printer::new(args.format, columns);
+    scan_each_result(options.clone(), |result| printer.print(&result)).await?;
+    printer.finish(&ScanMetadata {
+        started_at,
+        duration: start.elapsed(),
+        interfaces: &interfaces,
+        options: &options,
+    });
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{explain_backend, parse_dns_server, parse_duration, parse_ports, parse_resolvers};
+    use pingall::{BackendSelection, Capabilities, ResolverKind, ScanOptions};
+
+    #[test]
+    fn durations_accept_units_and_bare_seconds() {
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("3"), Ok(Duration::from_secs(3)));
+        assert!(parse_duration("-1s").is_err());
+        assert!(parse_duration("soon").is_err());
+    }
+
+    #[test]
+    fn resolver_lists_keep_their_order() {
+        assert_eq!(
+            parse_resolvers("dns, mdns"),
+            Ok(vec![ResolverKind::Dns, ResolverKind::Mdns])
+        );
+        assert_eq!(parse_resolvers("auto"), Ok(vec![]));
+        assert!(parse_resolvers("mdns,carrier-pigeon").is_err());
+    }
+
+    #[test]
+    fn ports_are_listed_with_commas() {
+        assert_eq!(parse_ports("22, 80,443"), Ok(vec![22, 80, 443]));
+        assert!(parse_ports("0").is_err());
+        assert!(parse_ports("22,ssh").is_err());
+        assert!(parse_ports("65536").is_err());
+        assert_eq!(parse_ports("top100").map(|ports| ports.len()), Ok(100));
+    }
+
+    #[test]
+    fn dns_servers_default_to_port_53() {
+        assert_eq!(
+            parse_dns_server("192.168.1.1"),
+            Ok("192.168.1.1:53".parse().unwrap())
+        );
+        assert_eq!(
+            parse_dns_server("[fd00::1]:5353"),
+            Ok("[fd00::1]:5353".parse().unwrap())
+        );
+        assert_eq!(
+            parse_dns_server("fd00::1"),
+            Ok("[fd00::1]:53".parse().unwrap())
+        );
+        assert!(parse_dns_server("router").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn backend_explanations_list_capabilities_and_the_choice() {
+        let selection = BackendSelection::with_capabilities(
+            Capabilities {
+                raw_icmp: true,
+                system_ping: true,
+                ..Capabilities::default()
+            },
+            &ScanOptions::default(),
+        );
+
+        let explanation = explain_backend(&selection);
+
+        assert!(explanation.contains("ICMP datagram sockets:  no\n"));
+        assert!(explanation.contains("backends, best first:   datagram, raw-socket, system\n"));
+        assert!(explanation.ends_with("chosen:                 raw-socket\n"));
+    }
+}
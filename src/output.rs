@@ -0,0 +1,729 @@
+//! Output formats for the command-line tool.
+
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use pingall::{Hostname, ScanOptions, ScanResult, Service, WatchEvent};
+
+/// Output format selected with `--format`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// `IP` or `IP<TAB>hostname` lines, as printed by earlier versions.
+    Plain,
+    /// Tab-separated values with a header and a fixed set of columns.
+    Tsv,
+    /// Comma-separated values with a header and a fixed set of columns.
+    Csv,
+    /// A single JSON document with scan metadata, printed when the scan ends.
+    Json,
+    /// One JSON object per host, printed as each host is found.
+    Ndjson,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Format::Plain),
+            "tsv" => Ok(Format::Tsv),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            _ => Err(format!(
+                "unknown format '{}', expected one of: plain, tsv, csv, json, ndjson",
+                s
+            )),
+        }
+    }
+}
+
+/// A field value, rendered either as JSON or as a CSV/TSV cell.
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(&'static str, Value)>),
+}
+
+impl Value {
+    fn string(value: impl ToString) -> Self {
+        Value::String(value.to_string())
+    }
+
+    fn number(value: impl ToString) -> Self {
+        Value::Number(value.to_string())
+    }
+
+    fn optional<T>(value: Option<T>, map: impl FnOnce(T) -> Value) -> Self {
+        value.map_or(Value::Null, map)
+    }
+
+    fn millis(duration: Duration) -> Self {
+        Value::Number(format!("{:.3}", duration.as_secs_f64() * 1000.0))
+    }
+
+    fn to_json(&self) -> String {
+        let mut json = String::new();
+        self.write_json(&mut json);
+        json
+    }
+
+    fn write_json(&self, json: &mut String) {
+        match self {
+            Value::Null => json.push_str("null"),
+            Value::Bool(value) => json.push_str(if *value { "true" } else { "false" }),
+            Value::Number(value) => json.push_str(value),
+            Value::String(value) => write_json_string(json, value),
+            Value::Array(values) => {
+                json.push('[');
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        json.push(',');
+                    }
+                    value.write_json(json);
+                }
+                json.push(']');
+            }
+            Value::Object(fields) => {
+                json.push('{');
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        json.push(',');
+                    }
+                    write_json_string(json, key);
+                    json.push(':');
+                    value.write_json(json);
+                }
+                json.push('}');
+            }
+        }
+    }
+
+    fn to_text(&self) -> String {
+        match self {
+            Value::Null => String::new(),
+            Value::Bool(value) => value.to_string(),
+            Value::Number(value) | Value::String(value) => value.clone(),
+            Value::Array(values) => values
+                .iter()
+                .map(Value::to_text)
+                .collect::<Vec<_>>()
+                .join(";"),
+            Value::Object(fields) => fields
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value.to_text()))
+                .collect::<Vec<_>>()
+                .join(","),
+        }
+    }
+}
+
+fn write_json_string(json: &mut String, value: &str) {
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", u32::from(c));
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+fn csv_cell(value: &Value) -> String {
+    let text = value.to_text();
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+fn tsv_cell(value: &Value) -> String {
+    value.to_text().replace(['\t', '\n', '\r'], " ")
+}
+
+/// Names of the per-host fields, in the order returned by [`result_fields`].
+const COLUMNS: &[&str] = &[
+    "ip",
+    "scope",
+    "hostname",
+    "hostnames",
+    "mac",
+    "vendor",
+    "rtt_ms",
+    "sent",
+    "received",
+    "loss_pct",
+    "rtt_min_ms",
+    "rtt_avg_ms",
+    "rtt_max_ms",
+    "rtt_mdev_ms",
+    "open_ports",
+    "services",
+    "probe",
+    "backend",
+];
+
+/// The per-host fields shared by every structured format.
+fn result_fields(result: &ScanResult) -> Vec<Value> {
+    let stats = result.stats.as_ref();
+    vec![
+        Value::string(result.ip_addr),
+        Value::optional(result.scope.as_ref(), Value::string),
+        Value::optional(result.hostname.as_ref(), Value::string),
+        Value::Array(
+            result
+                .hostnames
+                .iter()
+                .map(|hostname| {
+                    Value::Object(vec![
+                        ("name", Value::string(&hostname.name)),
+                        ("source", Value::string(&hostname.source)),
+                        (
+                            "confirmed",
+                            Value::optional(hostname.confirmed, Value::Bool),
+                        ),
+                    ])
+                })
+                .collect(),
+        ),
+        Value::optional(result.mac, Value::string),
+        Value::optional(result.vendor.as_ref(), Value::string),
+        Value::optional(result.rtt, Value::millis),
+        Value::optional(stats, |stats| Value::number(stats.sent)),
+        Value::optional(stats, |stats| Value::number(stats.received)),
+        Value::optional(stats, |stats| Value::Number(format!("{:.1}", stats.loss()))),
+        Value::optional(stats.and_then(|stats| stats.min), Value::millis),
+        Value::optional(stats.and_then(|stats| stats.avg), Value::millis),
+        Value::optional(stats.and_then(|stats| stats.max), Value::millis),
+        Value::optional(stats.and_then(|stats| stats.mdev), Value::millis),
+        Value::Array(result.open_ports.iter().map(Value::number).collect()),
+        Value::Array(
+            result
+                .services
+                .iter()
+                .map(|service| {
+                    Value::Object(vec![
+                        ("instance", Value::string(&service.instance)),
+                        ("type", Value::string(&service.service_type)),
+                        ("port", Value::number(service.port)),
+                        (
+                            "txt",
+                            Value::Array(service.txt.iter().map(Value::string).collect()),
+                        ),
+                    ])
+                })
+                .collect(),
+        ),
+        Value::string(result.probe),
+        Value::string(result.backend),
+    ]
+}
+
+fn result_object(result: &ScanResult) -> Value {
+    Value::Object(COLUMNS.iter().copied().zip(result_fields(result)).collect())
+}
+
+/// Names of the watch event fields, in the order returned by [`event_fields`].
+fn event_columns() -> Vec<&'static str> {
+    let mut columns = vec!["time", "event"];
+    columns.extend(COLUMNS);
+    columns.extend(["previous_hostname", "previous_mac"]);
+    columns
+}
+
+/// The host's fields, prefixed with when and what happened to it.
+fn event_fields(event: &WatchEvent, time: SystemTime) -> Vec<Value> {
+    let previous = match event {
+        WatchEvent::Changed { previous, .. } => Some(previous),
+        _ => None,
+    };
+
+    let mut fields = vec![Value::String(rfc3339(time)), Value::string(event.kind())];
+    fields.extend(result_fields(event.result()));
+    fields.push(Value::optional(
+        previous.and_then(|previous| previous.hostname.as_ref()),
+        Value::string,
+    ));
+    fields.push(Value::optional(
+        previous.and_then(|previous| previous.mac),
+        Value::string,
+    ));
+    fields
+}
+
+fn event_object(event: &WatchEvent, time: SystemTime) -> Value {
+    Value::Object(
+        event_columns()
+            .into_iter()
+            .zip(event_fields(event, time))
+            .collect(),
+    )
+}
+
+/// Scan metadata included in the `json` format.
+pub struct ScanMetadata<'a> {
+    pub started_at: SystemTime,
+    pub duration: Duration,
+    pub interfaces: &'a [String],
+    pub options: &'a ScanOptions,
+}
+
+fn options_value(options: &ScanOptions) -> Value {
+    Value::Object(vec![
+        (
+            "interface",
+            Value::optional(options.interface.as_ref(), Value::string),
+        ),
+        (
+            "targets",
+            Value::Array(options.targets.iter().map(Value::string).collect()),
+        ),
+        ("resolve_hostnames", Value::Bool(options.resolve_hostnames)),
+        (
+            "resolvers",
+            Value::Array(options.resolvers.iter().map(Value::string).collect()),
+        ),
+        (
+            "custom_resolvers",
+            Value::Array(
+                options
+                    .custom_resolvers
+                    .iter()
+                    .map(|resolver| Value::string(resolver.name()))
+                    .collect(),
+            ),
+        ),
+        ("resolve_mode", Value::string(options.resolve_mode)),
+        ("confirm_names", Value::Bool(options.confirm_names)),
+        (
+            "dns_servers",
+            Value::Array(options.dns_servers.iter().map(Value::string).collect()),
+        ),
+        (
+            "hosts_files",
+            Value::Array(
+                options
+                    .hosts_files
+                    .iter()
+                    .map(|path| Value::string(path.display()))
+                    .collect(),
+            ),
+        ),
+        ("lookup_mac", Value::Bool(options.lookup_mac)),
+        ("lookup_vendors", Value::Bool(options.lookup_vendors)),
+        (
+            "oui_file",
+            Value::optional(options.oui_file.as_ref(), |path| {
+                Value::string(path.display())
+            }),
+        ),
+        ("raw_socket", Value::Bool(options.raw_socket)),
+        ("method", Value::optional(options.method, Value::string)),
+        (
+            "tcp_ports",
+            Value::Array(options.tcp_ports.iter().map(Value::number).collect()),
+        ),
+        (
+            "ports",
+            Value::Array(options.ports.iter().map(Value::number).collect()),
+        ),
+        ("udp_discovery", Value::Bool(options.udp_discovery)),
+        ("browse_services", Value::Bool(options.browse_services)),
+        ("timeout_ms", Value::millis(options.effective_timeout())),
+        ("count", Value::number(options.count)),
+        ("interval_ms", Value::millis(options.interval)),
+        ("ipv4", Value::Bool(options.ipv4)),
+        ("ipv6", Value::Bool(options.ipv6)),
+        ("concurrency", Value::number(options.concurrency)),
+        (
+            "resolve_concurrency",
+            Value::number(options.resolve_concurrency),
+        ),
+        ("rate", Value::optional(options.rate, Value::number)),
+        ("adaptive", Value::Bool(options.adaptive)),
+        ("max_subnet_hosts", Value::number(options.max_subnet_hosts)),
+    ])
+}
+
+/// Optional columns of the `plain` format, printed between the address and
+/// the hostname. The structured formats always include every field.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PlainColumns {
+    /// Hardware address.
+    pub mac: bool,
+    /// Vendor of the hardware address.
+    pub vendor: bool,
+    /// Round-trip time.
+    pub rtt: bool,
+    /// Received/sent replies, loss and min/avg/max/mdev round-trip times.
+    pub stats: bool,
+    /// Every distinct hostname instead of only the first.
+    pub all_names: bool,
+    /// Open TCP ports, comma-separated.
+    pub open_ports: bool,
+    /// Advertised services, one indented line each below the host.
+    pub services: bool,
+}
+
+fn plain_millis(duration: Option<Duration>) -> String {
+    duration.map_or_else(
+        || "-".to_string(),
+        |duration| format!("{:.3}", duration.as_secs_f64() * 1000.0),
+    )
+}
+
+fn plain_line(result: &ScanResult, columns: PlainColumns) -> String {
+    let mut line = result.display_addr();
+    if columns.mac {
+        line.push('\t');
+        line.push_str(
+            &result
+                .mac
+                .map_or_else(|| "-".to_string(), |mac| mac.to_string()),
+        );
+    }
+    if columns.vendor {
+        line.push('\t');
+        line.push_str(result.vendor.as_deref().unwrap_or("-"));
+    }
+    if columns.rtt {
+        line.push_str(&format!("\t{} ms", plain_millis(result.rtt)));
+    }
+    if columns.stats
+        && let Some(stats) = &result.stats
+    {
+        line.push_str(&format!(
+            "\t{}/{} received, {:.1}% loss\t{}/{}/{}/{} ms",
+            stats.received,
+            stats.sent,
+            stats.loss(),
+            plain_millis(stats.min),
+            plain_millis(stats.avg),
+            plain_millis(stats.max),
+            plain_millis(stats.mdev),
+        ));
+    }
+    if columns.open_ports {
+        let ports: Vec<String> = result.open_ports.iter().map(u16::to_string).collect();
+        line.push('\t');
+        line.push_str(&if ports.is_empty() {
+            "-".to_string()
+        } else {
+            ports.join(",")
+        });
+    }
+    let mut names = plain_names(&result.hostnames, columns.all_names);
+    if names.is_empty()
+        && let Some(hostname) = &result.hostname
+    {
+        names.push(hostname.clone());
+    }
+    if !names.is_empty() {
+        line.push('\t');
+        line.push_str(&names.join(", "));
+    }
+    if columns.services {
+        for service in plain_services(&result.services) {
+            line.push('\n');
+            line.push_str(&service);
+        }
+    }
+
+    line
+}
+
+/// `\ttype\tport\tinstance`, followed by the TXT strings, for each service.
+fn plain_services(services: &[Service]) -> Vec<String> {
+    services
+        .iter()
+        .map(|service| {
+            let mut line = format!(
+                "\t{}\t{}\t{}",
+                service.service_type, service.port, service.instance
+            );
+            if !service.txt.is_empty() {
+                line.push('\t');
+                line.push_str(&service.txt.join(" "));
+            }
+            line
+        })
+        .collect()
+}
+
+/// The first name, or every distinct name, with `(unconfirmed)` after those
+/// that forward confirmation rejected.
+fn plain_names(hostnames: &[Hostname], all_names: bool) -> Vec<String> {
+    // A name found by several resolvers is flagged only if none confirmed it.
+    let mut names: Vec<(&str, bool)> = Vec::new();
+    for hostname in hostnames {
+        let unconfirmed = hostname.confirmed == Some(false);
+        match names
+            .iter_mut()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&hostname.name))
+        {
+            Some((_, flagged)) => *flagged &= unconfirmed,
+            None => names.push((&hostname.name, unconfirmed)),
+        }
+    }
+    if !all_names {
+        names.truncate(1);
+    }
+
+    names
+        .into_iter()
+        .map(|(name, unconfirmed)| match unconfirmed {
+            true => format!("{} (unconfirmed)", name),
+            false => name.to_string(),
+        })
+        .collect()
+}
+
+fn plain_event_line(event: &WatchEvent, time: SystemTime, columns: PlainColumns) -> String {
+    let mut line = format!(
+        "{}\t{}\t{}",
+        rfc3339(time),
+        event.kind().to_uppercase(),
+        plain_line(event.result(), columns)
+    );
+    if let WatchEvent::Changed { previous, current } = event {
+        let mut changes = Vec::new();
+        if previous.hostname != current.hostname {
+            changes.push(format!(
+                "hostname was {}",
+                previous.hostname.as_deref().unwrap_or("-")
+            ));
+        }
+        if previous.mac != current.mac {
+            changes.push(format!(
+                "mac was {}",
+                previous
+                    .mac
+                    .map_or_else(|| "-".to_string(), |mac| mac.to_string())
+            ));
+        }
+        line.push_str(&format!("\t({})", changes.join(", ")));
+    }
+
+    line
+}
+
+/// Prints scan results in the selected format.
+pub struct Printer {
+    format: Format,
+    columns: PlainColumns,
+    hosts: Vec<Value>,
+}
+
+impl Printer {
+    /// Create a printer, printing the header row for tabular formats.
+    pub fn new(format: Format, columns: PlainColumns) -> Self {
+        match format {
+            Format::Tsv => println!("{}", COLUMNS.join("\t")),
+            Format::Csv => println!("{}", COLUMNS.join(",")),
+            Format::Plain | Format::Json | Format::Ndjson => {}
+        }
+
+        Self {
+            format,
+            columns,
+            hosts: Vec::new(),
+        }
+    }
+
+    /// Create a printer for [`WatchEvent`]s, printing the header row for tabular formats.
+    pub fn for_events(format: Format, columns: PlainColumns) -> Self {
+        match format {
+            Format::Tsv => println!("{}", event_columns().join("\t")),
+            Format::Csv => println!("{}", event_columns().join(",")),
+            Format::Plain | Format::Json | Format::Ndjson => {}
+        }
+
+        Self {
+            format,
+            columns,
+            hosts: Vec::new(),
+        }
+    }
+
+    /// Print a watch event as soon as it happens.
+    ///
+    /// A watch never finishes, so `json` prints one object per line like `ndjson`.
+    pub fn print_event(&mut self, event: &WatchEvent) {
+        let time = SystemTime::now();
+        match self.format {
+            Format::Plain => println!("{}", plain_event_line(event, time, self.columns)),
+            Format::Tsv => println!("{}", row(&event_fields(event, time), tsv_cell, "\t")),
+            Format::Csv => println!("{}", row(&event_fields(event, time), csv_cell, ",")),
+            Format::Json | Format::Ndjson => println!("{}", event_object(event, time).to_json()),
+        }
+    }
+
+    /// Print a single host, or hold it until [`Printer::finish`] for `json`.
+    pub fn print(&mut self, result: &ScanResult) {
+        match self.format {
+            Format::Plain => println!("{}", plain_line(result, self.columns)),
+            Format::Tsv => println!("{}", row(&result_fields(result), tsv_cell, "\t")),
+            Format::Csv => println!("{}", row(&result_fields(result), csv_cell, ",")),
+            Format::Json => self.hosts.push(result_object(result)),
+            Format::Ndjson => println!("{}", result_object(result).to_json()),
+        }
+    }
+
+    /// Finish the output, printing the whole document for `json`.
+    pub fn finish(self, metadata: &ScanMetadata) {
+        if self.format != Format::Json {
+            return;
+        }
+
+        let document = Value::Object(vec![
+            ("started_at", Value::String(rfc3339(metadata.started_at))),
+            ("duration_ms", Value::millis(metadata.duration)),
+            (
+                "interfaces",
+                Value::Array(metadata.interfaces.iter().map(Value::string).collect()),
+            ),
+            ("options", options_value(metadata.options)),
+            ("hosts", Value::Array(self.hosts)),
+        ]);
+        println!("{}", document.to_json());
+    }
+}
+
+fn row(fields: &[Value], cell: fn(&Value) -> String, separator: &str) -> String {
+    fields.iter().map(cell).collect::<Vec<_>>().join(separator)
+}
+
+/// Format a timestamp as an RFC 3339 UTC date-time with millisecond precision.
+fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, seconds_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Civil date from days since the epoch, after Howard Hinnant's `civil_from_days`.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use pingall::{Hostname, Service};
+
+    use super::{Value, csv_cell, plain_names, plain_services, rfc3339, tsv_cell};
+
+    #[test]
+    fn json_strings_are_escaped() {
+        let value = Value::Object(vec![
+            ("name", Value::string("a \"quoted\"\tname\\")),
+            ("missing", Value::Null),
+        ]);
+
+        assert_eq!(
+            value.to_json(),
+            r#"{"name":"a \"quoted\"\tname\\","missing":null}"#
+        );
+    }
+
+    #[test]
+    fn csv_cells_are_quoted_only_when_needed() {
+        assert_eq!(csv_cell(&Value::string("printer.local")), "printer.local");
+        assert_eq!(csv_cell(&Value::string("a,\"b\"")), "\"a,\"\"b\"\"\"");
+        assert_eq!(csv_cell(&Value::Null), "");
+    }
+
+    #[test]
+    fn tsv_cells_never_contain_separators() {
+        assert_eq!(tsv_cell(&Value::string("a\tb\nc")), "a b c");
+    }
+
+    #[test]
+    fn plain_names_flag_names_no_resolver_confirmed() {
+        let hostname = |name: &str, source: &str, confirmed| Hostname {
+            name: name.to_string(),
+            source: source.to_string(),
+            confirmed,
+        };
+        let mut hostnames = vec![
+            hostname("nas.local", "mdns", Some(false)),
+            hostname("NAS.local", "dns", Some(true)),
+            hostname("old-nas.lan", "dns", Some(false)),
+            hostname("NAS", "netbios", None),
+        ];
+
+        assert_eq!(
+            plain_names(&hostnames, true),
+            ["nas.local", "old-nas.lan (unconfirmed)", "NAS"]
+        );
+        hostnames.remove(1);
+        assert_eq!(plain_names(&hostnames, false), ["nas.local (unconfirmed)"]);
+    }
+
+    #[test]
+    fn plain_services_list_type_port_instance_and_txt() {
+        let service = |instance: &str, service_type: &str, port, txt: &[&str]| Service {
+            instance: instance.to_string(),
+            service_type: service_type.to_string(),
+            port,
+            txt: txt.iter().map(|txt| txt.to_string()).collect(),
+        };
+        let services = [
+            service(
+                "Office Printer",
+                "_ipp._tcp",
+                631,
+                &["rp=ipp/print", "ty=Laser"],
+            ),
+            service("nas", "_smb._tcp", 445, &[]),
+        ];
+
+        assert_eq!(
+            plain_services(&services),
+            [
+                "\t_ipp._tcp\t631\tOffice Printer\trp=ipp/print ty=Laser",
+                "\t_smb._tcp\t445\tnas"
+            ]
+        );
+    }
+
+    #[test]
+    fn timestamps_are_rfc3339_utc() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + Duration::from_millis(1_792_323_045_123)),
+            "2026-10-18T11:30:45.123Z"
+        );
+    }
+}
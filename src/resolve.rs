@@ -0,0 +1,325 @@
+use std::fmt;
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::Hostname;
+use crate::util::clean_hostname;
+
+/// Future returned by [`Resolver::resolve`].
+pub type ResolveFuture<'a> = Pin<Box<dyn Future<Output = Vec<String>> + Send + 'a>>;
+
+/// Future returned by [`Resolver::confirm`].
+pub type ConfirmFuture<'a> = Pin<Box<dyn Future<Output = Option<bool>> + Send + 'a>>;
+
+/// A source of hostnames for the hosts a scan finds.
+///
+/// Implement this to supply names from elsewhere, such as an inventory
+/// service, and add it to [`ScanOptions::custom_resolvers`](crate::ScanOptions::custom_resolvers)
+/// as a [`CustomResolver`].
+pub trait Resolver: Send + Sync {
+    /// Short label for the names this resolver finds, e.g. `mdns`.
+    fn name(&self) -> &str;
+
+    /// Look up the names of `ip_addr`, best first.
+    ///
+    /// `scope` is the interface the host was found on, when known. An empty
+    /// list means the resolver has no name for the host.
+    fn resolve<'a>(&'a self, ip_addr: IpAddr, scope: Option<&'a str>) -> ResolveFuture<'a>;
+
+    /// Look `name`, which this resolver returned for `ip_addr`, back up and
+    /// check that it still leads to `ip_addr`.
+    ///
+    /// `None` means the name could not be checked. The default asks the
+    /// operating system's resolver.
+    fn confirm<'a>(
+        &'a self,
+        name: &'a str,
+        ip_addr: IpAddr,
+        _scope: Option<&'a str>,
+    ) -> ConfirmFuture<'a> {
+        Box::pin(system_confirm(name, ip_addr))
+    }
+}
+
+/// A [`Resolver`] added to a scan with
+/// [`ScanOptions::custom_resolvers`](crate::ScanOptions::custom_resolvers).
+///
+/// Resolvers have no notion of equality, so two are equal only when they are
+/// the same instance.
+#[derive(Clone)]
+pub struct CustomResolver(Arc<dyn Resolver>);
+
+impl CustomResolver {
+    pub fn new(resolver: impl Resolver + 'static) -> Self {
+        Self(Arc::new(resolver))
+    }
+}
+
+impl From<Arc<dyn Resolver>> for CustomResolver {
+    fn from(resolver: Arc<dyn Resolver>) -> Self {
+        Self(resolver)
+    }
+}
+
+impl std::ops::Deref for CustomResolver {
+    type Target = Arc<dyn Resolver>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Debug for CustomResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CustomResolver")
+            .field(&self.0.name())
+            .finish()
+    }
+}
+
+impl PartialEq for CustomResolver {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CustomResolver {}
+
+/// Whether the operating system's resolver maps `name` to `ip_addr`. A name it
+/// cannot find is unconfirmed.
+pub(crate) async fn system_confirm(name: &str, ip_addr: IpAddr) -> Option<bool> {
+    let confirmed = match tokio::net::lookup_host((name, 0)).await {
+        Ok(mut addresses) => addresses.any(|address| address.ip() == ip_addr),
+        Err(_) => false,
+    };
+    Some(confirmed)
+}
+
+/// Built-in hostname resolvers.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ResolverKind {
+    /// `avahi-resolve` on Linux, or the operating system's reverse lookup on Windows.
+    System,
+    /// Multicast DNS queries sent by `pingall` itself on each interface.
+    Mdns,
+    /// PTR queries sent by `pingall` itself to DNS servers.
+    Dns,
+    /// NetBIOS node status queries, answered by Windows machines and Samba
+    /// servers with `WORKGROUP\NAME`. IPv4 only.
+    Netbios,
+    /// `/etc/hosts`, DHCP server lease files and
+    /// [`ScanOptions::hosts_files`](crate::ScanOptions::hosts_files).
+    Hosts,
+}
+
+impl fmt::Display for ResolverKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ResolverKind::System => "system",
+            ResolverKind::Mdns => "mdns",
+            ResolverKind::Dns => "dns",
+            ResolverKind::Netbios => "netbios",
+            ResolverKind::Hosts => "hosts",
+        })
+    }
+}
+
+impl FromStr for ResolverKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "system" | "avahi" => Ok(ResolverKind::System),
+            "mdns" => Ok(ResolverKind::Mdns),
+            "dns" => Ok(ResolverKind::Dns),
+            "netbios" => Ok(ResolverKind::Netbios),
+            "hosts" => Ok(ResolverKind::Hosts),
+            _ => Err(format!(
+                "unknown resolver '{}', expected one of: system, mdns, dns, netbios, hosts",
+                s
+            )),
+        }
+    }
+}
+
+/// How many resolvers of the chain are asked about each host.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ResolveMode {
+    /// Stop at the first resolver that finds a name.
+    #[default]
+    First,
+    /// Ask every resolver and keep all the names they find.
+    All,
+}
+
+impl fmt::Display for ResolveMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ResolveMode::First => "first",
+            ResolveMode::All => "all",
+        })
+    }
+}
+
+impl FromStr for ResolveMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(ResolveMode::First),
+            "all" => Ok(ResolveMode::All),
+            _ => Err(format!(
+                "unknown resolve mode '{}', expected one of: first, all",
+                s
+            )),
+        }
+    }
+}
+
+/// The resolvers of a scan, asked in order.
+pub(crate) struct ResolverChain {
+    pub(crate) resolvers: Vec<Arc<dyn Resolver>>,
+    pub(crate) mode: ResolveMode,
+    /// Forward-confirm each name with the resolver that found it.
+    pub(crate) confirm: bool,
+}
+
+impl ResolverChain {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.resolvers.is_empty()
+    }
+
+    /// Names of `ip_addr`, labelled with the resolver that found them.
+    pub(crate) async fn resolve(&self, ip_addr: IpAddr, scope: Option<&str>) -> Vec<Hostname> {
+        let mut hostnames = Vec::new();
+        for resolver in &self.resolvers {
+            for name in resolver.resolve(ip_addr, scope).await {
+                let Some(name) = clean_hostname(&ip_addr, &name) else {
+                    continue;
+                };
+                let confirmed = if self.confirm {
+                    resolver.confirm(&name, ip_addr, scope).await
+                } else {
+                    None
+                };
+                hostnames.push(Hostname {
+                    name,
+                    source: resolver.name().to_string(),
+                    confirmed,
+                });
+            }
+            if self.mode == ResolveMode::First && !hostnames.is_empty() {
+                break;
+            }
+        }
+
+        hostnames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+
+    use super::{ConfirmFuture, ResolveFuture, ResolveMode, Resolver, ResolverChain};
+    use crate::Hostname;
+
+    struct Fixed(&'static str, &'static [&'static str]);
+
+    impl Resolver for Fixed {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn resolve<'a>(&'a self, _ip_addr: IpAddr, _scope: Option<&'a str>) -> ResolveFuture<'a> {
+            Box::pin(async move { self.1.iter().map(|name| name.to_string()).collect() })
+        }
+
+        /// Only the first name of each resolver still points at the host.
+        fn confirm<'a>(
+            &'a self,
+            name: &'a str,
+            _ip_addr: IpAddr,
+            _scope: Option<&'a str>,
+        ) -> ConfirmFuture<'a> {
+            Box::pin(async move {
+                let first = self.1.iter().find(|name| !name.is_empty());
+                Some(first.map(|first| first.trim_end_matches('.')) == Some(name))
+            })
+        }
+    }
+
+    fn resolve(mode: ResolveMode) -> Vec<Hostname> {
+        resolve_with(mode, false)
+    }
+
+    fn resolve_with(mode: ResolveMode, confirm: bool) -> Vec<Hostname> {
+        let chain = ResolverChain {
+            resolvers: vec![
+                Arc::new(Fixed("inventory", &[])),
+                Arc::new(Fixed("mdns", &["nas.local."])),
+                Arc::new(Fixed(
+                    "dns",
+                    &["", "nas.example.com", "old-nas.example.com"],
+                )),
+            ],
+            mode,
+            confirm,
+        };
+        let ip_addr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10));
+
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(chain.resolve(ip_addr, None))
+    }
+
+    fn hostname(name: &str, source: &str) -> Hostname {
+        Hostname {
+            name: name.to_string(),
+            source: source.to_string(),
+            confirmed: None,
+        }
+    }
+
+    #[test]
+    fn the_first_resolver_with_a_name_wins() {
+        assert_eq!(
+            resolve(ResolveMode::First),
+            vec![hostname("nas.local", "mdns")]
+        );
+    }
+
+    #[test]
+    fn all_names_are_collected_in_chain_order() {
+        assert_eq!(
+            resolve(ResolveMode::All),
+            vec![
+                hostname("nas.local", "mdns"),
+                hostname("nas.example.com", "dns"),
+                hostname("old-nas.example.com", "dns")
+            ]
+        );
+    }
+
+    #[test]
+    fn confirmation_flags_names_that_no_longer_match() {
+        let confirmed: Vec<_> = resolve_with(ResolveMode::All, true)
+            .into_iter()
+            .map(|hostname| (hostname.name, hostname.confirmed))
+            .collect();
+
+        assert_eq!(
+            confirmed,
+            vec![
+                ("nas.local".to_string(), Some(true)),
+                ("nas.example.com".to_string(), Some(true)),
+                ("old-nas.example.com".to_string(), Some(false)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::{MacAddr, PingBackend};
+
+/// How a host was discovered.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Probe {
+    /// Unicast echo to every address of a local subnet or explicit target.
+    Sweep,
+    /// Echo to the scoped IPv6 all-nodes multicast address of an interface.
+    Ipv6Multicast,
+    /// SSDP `M-SEARCH` for all devices, answered by UPnP devices such as
+    /// smart TVs and media players.
+    Ssdp,
+    /// Multicast DNS query for `_services._dns-sd._udp.local`, answered by
+    /// devices that advertise services, such as printers and Chromecasts.
+    Mdns,
+    /// NetBIOS node status query to the IPv4 broadcast address, answered by
+    /// Windows machines and Samba servers.
+    Netbios,
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Probe::Sweep => "sweep",
+            Probe::Ipv6Multicast => "ipv6-multicast",
+            Probe::Ssdp => "ssdp",
+            Probe::Mdns => "mdns",
+            Probe::Netbios => "netbios",
+        })
+    }
+}
+
+/// Reply statistics for a host probed one or more times.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PingStats {
+    /// Number of echo requests sent.
+    pub sent: usize,
+    /// Number of replies received.
+    pub received: usize,
+    /// Shortest round-trip time.
+    pub min: Option<Duration>,
+    /// Mean round-trip time.
+    pub avg: Option<Duration>,
+    /// Longest round-trip time.
+    pub max: Option<Duration>,
+    /// Mean deviation of the round-trip times, as reported by `ping`.
+    pub mdev: Option<Duration>,
+}
+
+impl PingStats {
+    /// Summarize `received` replies to `sent` requests with the measured round-trip times.
+    ///
+    /// `rtts` may hold fewer entries than `received` when a backend could not
+    /// measure every reply.
+    pub(crate) fn new(sent: usize, received: usize, rtts: &[Duration]) -> Self {
+        let secs: Vec<f64> = rtts.iter().map(Duration::as_secs_f64).collect();
+        let (avg, mdev) = if secs.is_empty() {
+            (None, None)
+        } else {
+            let mean = secs.iter().sum::<f64>() / secs.len() as f64;
+            let mean_square = secs.iter().map(|rtt| rtt * rtt).sum::<f64>() / secs.len() as f64;
+            let mdev = (mean_square - mean * mean).max(0.0).sqrt();
+            (
+                Some(Duration::from_secs_f64(mean)),
+                Some(Duration::from_secs_f64(mdev)),
+            )
+        };
+
+        Self {
+            sent,
+            received,
+            min: rtts.iter().min().copied(),
+            avg,
+            max: rtts.iter().max().copied(),
+            mdev,
+        }
+    }
+
+    /// Percentage of requests that went unanswered.
+    pub fn loss(&self) -> f64 {
+        if self.sent == 0 {
+            0.0
+        } else {
+            self.sent.saturating_sub(self.received) as f64 * 100.0 / self.sent as f64
+        }
+    }
+}
+
+/// A name found for a host.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Hostname {
+    /// The name, without a trailing dot.
+    pub name: String,
+    /// [`Resolver::name`](crate::Resolver::name) of the resolver that found it.
+    pub source: String,
+    /// Whether looking the name back up led to the host's address. `None`
+    /// when [`ScanOptions::confirm_names`](crate::ScanOptions::confirm_names)
+    /// is off or the resolver cannot check its names.
+    pub confirmed: Option<bool>,
+}
+
+/// A DNS-SD service instance a host advertises over multicast DNS.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Service {
+    /// Instance name, e.g. `Office Printer`.
+    pub instance: String,
+    /// Service type, e.g. `_ipp._tcp`.
+    pub service_type: String,
+    /// Port the service listens on.
+    pub port: u16,
+    /// Strings of the TXT record, e.g. `rp=ipp/print`.
+    pub txt: Vec<String>,
+}
+
+/// A host that answered during a scan.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct ScanResult {
+    /// Address of the host.
+    pub ip_addr: IpAddr,
+    /// Interface or IPv6 zone the host was seen on, when known.
+    pub scope: Option<String>,
+    /// Resolved hostname, when resolution was requested and succeeded.
+    ///
+    /// This is the first of [`ScanResult::hostnames`].
+    pub hostname: Option<String>,
+    /// Every name the resolvers found, in resolver order.
+    ///
+    /// With [`ResolveMode::First`](crate::ResolveMode::First) these all come
+    /// from the first resolver that found a name.
+    pub hostnames: Vec<Hostname>,
+    /// Hardware address of the host, when the probe learned it.
+    pub mac: Option<MacAddr>,
+    /// Vendor registered for the hardware address, when vendor lookup was requested.
+    pub vendor: Option<String>,
+    /// Round-trip time of the reply, when the probe measured it.
+    ///
+    /// When a host is probed more than once this is the mean round-trip time.
+    pub rtt: Option<Duration>,
+    /// Reply statistics across all probes sent to the host.
+    pub stats: Option<PingStats>,
+    /// [`ScanOptions::ports`](crate::ScanOptions::ports) that accepted a
+    /// connection, in ascending order.
+    pub open_ports: Vec<u16>,
+    /// Services the host advertises, when
+    /// [`ScanOptions::browse_services`](crate::ScanOptions::browse_services) is set.
+    pub services: Vec<Service>,
+    /// Probe that discovered the host.
+    pub probe: Probe,
+    /// Backend that received the reply.
+    pub backend: PingBackend,
+}
+
+impl ScanResult {
+    pub(crate) fn new(ip_addr: IpAddr, probe: Probe, backend: PingBackend) -> Self {
+        Self {
+            ip_addr,
+            scope: None,
+            hostname: None,
+            hostnames: Vec::new(),
+            mac: None,
+            vendor: None,
+            rtt: None,
+            stats: None,
+            open_ports: Vec::new(),
+            services: Vec::new(),
+            probe,
+            backend,
+        }
+    }
+
+    /// The address as printed by the CLI.
+    ///
+    /// IPv6 addresses include their zone, e.g. `fe80::1%eth0`, so that they
+    /// can be used directly. IPv4 addresses never do.
+    pub fn display_addr(&self) -> String {
+        match (&self.ip_addr, &self.scope) {
+            (IpAddr::V6(ip_addr), Some(scope)) => format!("{}%{}", ip_addr, scope),
+            (ip_addr, _) => ip_addr.to_string(),
+        }
+    }
+}
+
+/// Formats the result as `IP` or `IP<TAB>hostname`, as printed by the CLI.
+impl fmt::Display for ScanResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.hostname {
+            Some(hostname) => write!(f, "{}\t{}", self.display_addr(), hostname),
+            None => write!(f, "{}", self.display_addr()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use std::time::Duration;
+
+    use super::{PingStats, Probe, ScanResult};
+    use crate::PingBackend;
+
+    #[test]
+    fn ping_stats_summarize_round_trip_times() {
+        let stats = PingStats::new(
+            4,
+            3,
+            &[
+                Duration::from_millis(1),
+                Duration::from_millis(2),
+                Duration::from_millis(3),
+            ],
+        );
+
+        assert_eq!(stats.loss(), 25.0);
+        assert_eq!(stats.min, Some(Duration::from_millis(1)));
+        assert_eq!(stats.max, Some(Duration::from_millis(3)));
+        assert_eq!(stats.avg.map(|avg| avg.as_micros()), Some(2000));
+        assert_eq!(stats.mdev.map(|mdev| mdev.as_micros()), Some(816));
+    }
+
+    #[test]
+    fn results_format_as_ip_and_hostname() {
+        let mut result = ScanResult::new(
+            IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10)),
+            Probe::Sweep,
+            PingBackend::System,
+        );
+        result.scope = Some("eth0".to_string());
+        assert_eq!(result.to_string(), "192.168.1.10");
+
+        result.hostname = Some("printer.local".to_string());
+        assert_eq!(result.to_string(), "192.168.1.10\tprinter.local");
+    }
+
+    #[test]
+    fn ipv6_results_keep_their_zone() {
+        let mut result = ScanResult::new(
+            "fe80::1".parse().unwrap(),
+            Probe::Ipv6Multicast,
+            PingBackend::RawSocket,
+        );
+        result.scope = Some("wlan0".to_string());
+
+        assert_eq!(result.to_string(), "fe80::1%wlan0");
+    }
+}
@@ -0,0 +1,344 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/// An explicit scan target.
+///
+/// Targets are parsed from strings such as `10.20.0.0/20`, `192.168.5.10-40`,
+/// `192.168.5.10-192.168.5.40`, `192.168.5.7` or `host.example`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Target {
+    /// A single address.
+    Address(IpAddr),
+    /// Every host address of a CIDR network.
+    Network { address: IpAddr, prefix_len: u8 },
+    /// An inclusive range of addresses.
+    Range { start: IpAddr, end: IpAddr },
+    /// A hostname, resolved to all of its addresses when the scan starts.
+    Host(String),
+}
+
+/// Error returned when a string is not a valid [`Target`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseTargetError {
+    target: String,
+    reason: &'static str,
+}
+
+impl fmt::Display for ParseTargetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid target '{}': {}", self.target, self.reason)
+    }
+}
+
+impl std::error::Error for ParseTargetError {}
+
+impl FromStr for Target {
+    type Err = ParseTargetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |reason| ParseTargetError {
+            target: s.to_string(),
+            reason,
+        };
+
+        if let Some((address, prefix_len)) = s.split_once('/') {
+            let address = address
+                .parse::<IpAddr>()
+                .map_err(|_| error("network address is not an IP address"))?;
+            let max_prefix_len = if address.is_ipv4() { 32 } else { 128 };
+            let prefix_len = prefix_len
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= max_prefix_len)
+                .ok_or_else(|| error("invalid prefix length"))?;
+            return Ok(Target::Network {
+                address,
+                prefix_len,
+            });
+        }
+
+        if let Ok(address) = s.parse::<IpAddr>() {
+            return Ok(Target::Address(address));
+        }
+
+        if let Some((start, end)) = s.split_once('-')
+            && let Ok(start) = start.parse::<IpAddr>()
+        {
+            let end = match (start, end.parse::<IpAddr>(), end.parse::<u8>()) {
+                (_, Ok(end), _) => end,
+                (IpAddr::V4(start), _, Ok(last_octet)) => {
+                    let [a, b, c, _] = start.octets();
+                    IpAddr::V4(Ipv4Addr::new(a, b, c, last_octet))
+                }
+                _ => return Err(error("invalid range end")),
+            };
+            if start.is_ipv4() != end.is_ipv4() {
+                return Err(error("range mixes IPv4 and IPv6 addresses"));
+            }
+            if address_to_u128(start) > address_to_u128(end) {
+                return Err(error("range end is before its start"));
+            }
+            return Ok(Target::Range { start, end });
+        }
+
+        let valid_hostname = !s.is_empty()
+            && s.len() <= 253
+            && s.split('.').all(|label| {
+                !label.is_empty()
+                    && label.len() <= 63
+                    && !label.starts_with('-')
+                    && label
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            });
+        if valid_hostname {
+            Ok(Target::Host(s.to_string()))
+        } else {
+            Err(error("not an address, network, range or hostname"))
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Address(address) => write!(f, "{}", address),
+            Target::Network {
+                address,
+                prefix_len,
+            } => write!(f, "{}/{}", address, prefix_len),
+            Target::Range { start, end } => write!(f, "{}-{}", start, end),
+            Target::Host(host) => write!(f, "{}", host),
+        }
+    }
+}
+
+impl Target {
+    /// Number of addresses this target expands to, or `None` for hostnames.
+    pub(crate) fn host_count(&self) -> Option<u128> {
+        match self {
+            Target::Address(_) => Some(1),
+            Target::Network {
+                address,
+                prefix_len,
+            } => {
+                let (first, last) = network_bounds(*address, *prefix_len);
+                Some((last - first).saturating_add(1))
+            }
+            Target::Range { start, end } => {
+                Some((address_to_u128(*end) - address_to_u128(*start)).saturating_add(1))
+            }
+            Target::Host(_) => None,
+        }
+    }
+
+    /// Addresses covered by this target. Hostnames cover no addresses until resolved.
+    pub(crate) fn addresses(&self) -> impl Iterator<Item = IpAddr> + use<> {
+        let (template, first, last) = match self {
+            Target::Address(address) => {
+                let value = address_to_u128(*address);
+                (*address, value, value)
+            }
+            Target::Network {
+                address,
+                prefix_len,
+            } => {
+                let (first, last) = network_bounds(*address, *prefix_len);
+                (*address, first, last)
+            }
+            Target::Range { start, end } => {
+                (*start, address_to_u128(*start), address_to_u128(*end))
+            }
+            // An empty range.
+            Target::Host(_) => (IpAddr::V4(Ipv4Addr::UNSPECIFIED), 1, 0),
+        };
+
+        (first..=last).map(move |address| u128_to_address(template, address))
+    }
+}
+
+/// Build the network target for a local IPv4 subnet.
+///
+/// Subnets with more than `max_hosts` hosts are narrowed to the largest subnet
+/// around `address` that fits.
+pub(crate) fn local_ipv4_subnet(address: Ipv4Addr, prefix_len: u8, max_hosts: usize) -> Target {
+    let mut prefix_len = prefix_len.min(32);
+    let mut target = Target::Network {
+        address: IpAddr::V4(address),
+        prefix_len,
+    };
+    while prefix_len < 32 && target.host_count().unwrap_or(0) > max_hosts as u128 {
+        prefix_len += 1;
+        target = Target::Network {
+            address: IpAddr::V4(address),
+            prefix_len,
+        };
+    }
+
+    target
+}
+
+/// Check whether `candidate` is on the IPv4 subnet of `address`.
+pub(crate) fn ipv4_subnet_contains(address: Ipv4Addr, prefix_len: u8, candidate: Ipv4Addr) -> bool {
+    let mask = u32::MAX
+        .checked_shl(32 - u32::from(prefix_len.min(32)))
+        .unwrap_or(0);
+    u32::from(address) & mask == u32::from(candidate) & mask
+}
+
+/// First and last host address of a network.
+///
+/// The network and IPv4 broadcast addresses are skipped, except on IPv4 `/31`
+/// and `/32` and IPv6 `/127` and `/128` networks where every address is a host.
+fn network_bounds(address: IpAddr, prefix_len: u8) -> (u128, u128) {
+    let bits = if address.is_ipv4() { 32 } else { 128 };
+    let host_bits = bits - u32::from(prefix_len.min(bits as u8));
+    let size = 1u128.checked_shl(host_bits).unwrap_or(0);
+    let network = address_to_u128(address) & !size.wrapping_sub(1);
+    let last = network.wrapping_add(size.wrapping_sub(1));
+
+    match (address, host_bits) {
+        (_, 0 | 1) => (network, last),
+        (IpAddr::V4(_), _) => (network + 1, last - 1),
+        (IpAddr::V6(_), _) => (network + 1, last),
+    }
+}
+
+fn address_to_u128(address: IpAddr) -> u128 {
+    match address {
+        IpAddr::V4(address) => u128::from(u32::from(address)),
+        IpAddr::V6(address) => u128::from(address),
+    }
+}
+
+fn u128_to_address(template: IpAddr, address: u128) -> IpAddr {
+    match template {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(address as u32)),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(address)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::{Target, ipv4_subnet_contains, local_ipv4_subnet};
+
+    fn v4(a: u8, b: u8, c: u8, d: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(a, b, c, d))
+    }
+
+    #[test]
+    fn targets_parse_networks_ranges_addresses_and_hosts() {
+        assert_eq!(
+            "10.20.0.0/20".parse(),
+            Ok(Target::Network {
+                address: v4(10, 20, 0, 0),
+                prefix_len: 20,
+            })
+        );
+        assert_eq!(
+            "192.168.5.10-40".parse(),
+            Ok(Target::Range {
+                start: v4(192, 168, 5, 10),
+                end: v4(192, 168, 5, 40),
+            })
+        );
+        assert_eq!(
+            "192.168.5.10-192.168.6.1".parse(),
+            Ok(Target::Range {
+                start: v4(192, 168, 5, 10),
+                end: v4(192, 168, 6, 1),
+            })
+        );
+        assert_eq!(
+            "192.168.5.7".parse(),
+            Ok(Target::Address(v4(192, 168, 5, 7)))
+        );
+        assert_eq!(
+            "host.example".parse(),
+            Ok(Target::Host("host.example".to_string()))
+        );
+    }
+
+    #[test]
+    fn targets_reject_malformed_input() {
+        assert!("10.0.0.0/33".parse::<Target>().is_err());
+        assert!("192.168.5.40-10".parse::<Target>().is_err());
+        assert!("192.168.5.1-::1".parse::<Target>().is_err());
+        assert!("bad host".parse::<Target>().is_err());
+    }
+
+    #[test]
+    fn ipv4_subnets_contain_only_their_own_addresses() {
+        let local = Ipv4Addr::new(192, 168, 4, 10);
+
+        assert!(ipv4_subnet_contains(
+            local,
+            22,
+            Ipv4Addr::new(192, 168, 7, 200)
+        ));
+        assert!(!ipv4_subnet_contains(
+            local,
+            22,
+            Ipv4Addr::new(192, 168, 8, 1)
+        ));
+        assert!(ipv4_subnet_contains(local, 0, Ipv4Addr::new(10, 0, 0, 1)));
+    }
+
+    #[test]
+    fn ranges_expand_inclusively() {
+        let target: Target = "192.168.5.254-192.168.6.1".parse().unwrap();
+
+        assert_eq!(
+            target.addresses().collect::<Vec<_>>(),
+            vec![
+                v4(192, 168, 5, 254),
+                v4(192, 168, 5, 255),
+                v4(192, 168, 6, 0),
+                v4(192, 168, 6, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn ipv4_sweep_covers_hosts_of_the_real_prefix() {
+        let target = local_ipv4_subnet(Ipv4Addr::new(10, 0, 5, 77), 22, 1024);
+        let hosts: Vec<_> = target.addresses().collect();
+
+        assert_eq!(hosts.len(), 1022);
+        assert_eq!(hosts.first(), Some(&v4(10, 0, 4, 1)));
+        assert_eq!(hosts.last(), Some(&v4(10, 0, 7, 254)));
+    }
+
+    #[test]
+    fn ipv4_sweep_skips_network_and_broadcast_on_small_subnets() {
+        let target = local_ipv4_subnet(Ipv4Addr::new(192, 168, 1, 70), 26, 1024);
+        let hosts: Vec<_> = target.addresses().collect();
+
+        assert_eq!(hosts.len(), 62);
+        assert_eq!(hosts.first(), Some(&v4(192, 168, 1, 65)));
+        assert_eq!(hosts.last(), Some(&v4(192, 168, 1, 126)));
+    }
+
+    #[test]
+    fn ipv4_sweep_keeps_both_addresses_of_point_to_point_subnets() {
+        let target = local_ipv4_subnet(Ipv4Addr::new(10, 0, 0, 1), 31, 1024);
+
+        assert_eq!(
+            target.addresses().collect::<Vec<_>>(),
+            vec![v4(10, 0, 0, 0), v4(10, 0, 0, 1)]
+        );
+    }
+
+    #[test]
+    fn ipv4_sweep_narrows_prefixes_that_exceed_the_host_cap() {
+        let target = local_ipv4_subnet(Ipv4Addr::new(10, 1, 2, 3), 8, 254);
+        let hosts: Vec<_> = target.addresses().collect();
+
+        assert_eq!(hosts.len(), 254);
+        assert_eq!(hosts.first(), Some(&v4(10, 1, 2, 1)));
+        assert_eq!(hosts.last(), Some(&v4(10, 1, 2, 254)));
+    }
+}
@@ -0,0 +1,359 @@
+//! ARP who-has discovery over a raw packet socket.
+//!
+//! Hosts that drop ICMP echo requests still have to answer ARP to be reachable
+//! at all, so sweeping a local subnet with ARP requests finds them too.
+
+#[cfg(not(target_os = "linux"))]
+use std::collections::BTreeMap;
+#[cfg(not(target_os = "linux"))]
+use std::io;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+#[cfg(not(target_os = "linux"))]
+use super::Throttle;
+use crate::MacAddr;
+
+const ETHERTYPE_ARP: u16 = 0x0806;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const HARDWARE_ETHERNET: u16 = 1;
+const OPERATION_REQUEST: u16 = 1;
+const OPERATION_REPLY: u16 = 2;
+const FRAME_LEN: usize = 42;
+
+/// A host that answered at least one ARP request.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct ArpResponder {
+    pub(crate) mac: MacAddr,
+    pub(crate) received: usize,
+    pub(crate) rtts: Vec<Duration>,
+}
+
+/// Build a broadcast Ethernet frame asking who has `target_ip`.
+fn request_frame(source_mac: MacAddr, source_ip: Ipv4Addr, target_ip: Ipv4Addr) -> [u8; FRAME_LEN] {
+    let mut frame = [0; FRAME_LEN];
+    frame[0..6].copy_from_slice(&[0xff; 6]);
+    frame[6..12].copy_from_slice(&source_mac.octets());
+    frame[12..14].copy_from_slice(&ETHERTYPE_ARP.to_be_bytes());
+    frame[14..16].copy_from_slice(&HARDWARE_ETHERNET.to_be_bytes());
+    frame[16..18].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+    frame[18] = 6;
+    frame[19] = 4;
+    frame[20..22].copy_from_slice(&OPERATION_REQUEST.to_be_bytes());
+    frame[22..28].copy_from_slice(&source_mac.octets());
+    frame[28..32].copy_from_slice(&source_ip.octets());
+    // The target hardware address (32..38) is left zeroed.
+    frame[38..42].copy_from_slice(&target_ip.octets());
+    frame
+}
+
+/// Extract the sender of an ARP reply frame.
+fn parse_reply(frame: &[u8]) -> Option<(Ipv4Addr, MacAddr)> {
+    let field = |offset: usize| u16::from_be_bytes([frame[offset], frame[offset + 1]]);
+
+    if frame.len() < FRAME_LEN
+        || field(12) != ETHERTYPE_ARP
+        || field(14) != HARDWARE_ETHERNET
+        || field(16) != ETHERTYPE_IPV4
+        || frame[18] != 6
+        || frame[19] != 4
+        || field(20) != OPERATION_REPLY
+    {
+        return None;
+    }
+
+    let mut mac = [0; 6];
+    mac.copy_from_slice(&frame[22..28]);
+    let ip = Ipv4Addr::new(frame[28], frame[29], frame[30], frame[31]);
+    Some((ip, MacAddr::new(mac)))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) use linux::{ArpSocket, packet_socket_permitted};
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::collections::BTreeMap;
+    use std::ffi::CString;
+    use std::io;
+    use std::net::Ipv4Addr;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::time::Duration;
+
+    use tokio::io::unix::AsyncFd;
+    use tokio::time::Instant;
+
+    use super::{ArpResponder, ETHERTYPE_ARP, parse_reply, request_frame};
+    use crate::MacAddr;
+    use crate::util::Throttle;
+
+    /// A packet socket bound to one interface, sending and receiving ARP frames.
+    pub(crate) struct ArpSocket {
+        fd: AsyncFd<OwnedFd>,
+        mac: MacAddr,
+        source: Ipv4Addr,
+    }
+
+    fn interface_mac(interface: &str) -> io::Result<MacAddr> {
+        let address = std::fs::read_to_string(format!("/sys/class/net/{}/address", interface))?;
+        address
+            .trim()
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Whether this process may open packet sockets, which needs
+    /// `CAP_NET_RAW`.
+    pub(crate) fn packet_socket_permitted() -> bool {
+        // SAFETY: plain socket(2) call; the result is checked before use.
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                i32::from(ETHERTYPE_ARP.to_be()),
+            )
+        };
+        if fd < 0 {
+            return false;
+        }
+        // SAFETY: `fd` is a freshly opened descriptor that nothing else owns.
+        drop(unsafe { OwnedFd::from_raw_fd(fd) });
+        true
+    }
+
+    impl ArpSocket {
+        /// Open a packet socket on `interface`, sending requests from `source`.
+        ///
+        /// Requires `CAP_NET_RAW`.
+        pub(crate) fn open(interface: &str, source: Ipv4Addr) -> io::Result<Self> {
+            let name = CString::new(interface)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            // SAFETY: `name` is a valid NUL-terminated string.
+            let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+            if index == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mac = interface_mac(interface)?;
+
+            // SAFETY: plain socket(2) call; the result is checked before use.
+            let fd = unsafe {
+                libc::socket(
+                    libc::AF_PACKET,
+                    libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                    i32::from(ETHERTYPE_ARP.to_be()),
+                )
+            };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: `fd` is a freshly opened descriptor that nothing else owns.
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+            // SAFETY: `sockaddr_ll` is plain old data, so all zeroes is a valid value.
+            let mut address: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+            address.sll_family = libc::AF_PACKET as u16;
+            address.sll_protocol = ETHERTYPE_ARP.to_be();
+            address.sll_ifindex = index as i32;
+            // SAFETY: `address` is a valid `sockaddr_ll` and the length matches it.
+            let bound = unsafe {
+                libc::bind(
+                    fd.as_raw_fd(),
+                    (&address as *const libc::sockaddr_ll).cast(),
+                    std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+                )
+            };
+            if bound < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(Self {
+                fd: AsyncFd::new(fd)?,
+                mac,
+                source,
+            })
+        }
+
+        async fn send_frame(&self, frame: &[u8]) -> io::Result<()> {
+            loop {
+                let mut guard = self.fd.writable().await?;
+                // SAFETY: `frame` is valid for reads of `frame.len()` bytes.
+                let sent = guard.try_io(|fd| {
+                    let sent = unsafe {
+                        libc::send(fd.as_raw_fd(), frame.as_ptr().cast(), frame.len(), 0)
+                    };
+                    if sent < 0 {
+                        Err(io::Error::last_os_error())
+                    } else {
+                        Ok(())
+                    }
+                });
+                if let Ok(result) = sent {
+                    return result;
+                }
+            }
+        }
+
+        /// Receive replies until `deadline`, crediting each to its latest request.
+        async fn receive_until(
+            &self,
+            deadline: Instant,
+            pending: &mut BTreeMap<Ipv4Addr, Option<Instant>>,
+            responders: &mut BTreeMap<Ipv4Addr, ArpResponder>,
+        ) -> io::Result<()> {
+            let mut buffer = [0u8; 1514];
+            loop {
+                let mut guard = match tokio::time::timeout_at(deadline, self.fd.readable()).await {
+                    Ok(guard) => guard?,
+                    Err(_) => return Ok(()),
+                };
+                // SAFETY: `buffer` is valid for writes of `buffer.len()` bytes.
+                let received = guard.try_io(|fd| {
+                    let received = unsafe {
+                        libc::recv(fd.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len(), 0)
+                    };
+                    if received < 0 {
+                        Err(io::Error::last_os_error())
+                    } else {
+                        Ok(received as usize)
+                    }
+                });
+                let Ok(received) = received else {
+                    continue;
+                };
+
+                let Some((ip, mac)) = parse_reply(&buffer[..received?]) else {
+                    continue;
+                };
+                // Only the first reply to each request counts.
+                if let Some(sent) = pending.get_mut(&ip).and_then(Option::take) {
+                    let responder = responders.entry(ip).or_insert(ArpResponder {
+                        mac,
+                        received: 0,
+                        rtts: Vec::new(),
+                    });
+                    responder.mac = mac;
+                    responder.received += 1;
+                    responder.rtts.push(sent.elapsed());
+                }
+            }
+        }
+
+        /// Send `count` rounds of requests to every target and collect the replies.
+        ///
+        /// Rounds start `interval` apart, requests are paced by `throttle`, and
+        /// replies are collected until `timeout` after the last round.
+        pub(crate) async fn sweep(
+            &self,
+            targets: &[Ipv4Addr],
+            timeout: Duration,
+            count: usize,
+            interval: Duration,
+            throttle: &Throttle,
+        ) -> io::Result<BTreeMap<Ipv4Addr, ArpResponder>> {
+            let mut pending = BTreeMap::new();
+            let mut responders = BTreeMap::new();
+
+            for round in 0..count {
+                let round_start = Instant::now();
+                for target in targets {
+                    throttle.pace().await;
+                    self.send_frame(&request_frame(self.mac, self.source, *target))
+                        .await?;
+                    pending.insert(*target, Some(Instant::now()));
+                }
+
+                let deadline = if round + 1 < count {
+                    round_start + interval
+                } else {
+                    Instant::now() + timeout
+                };
+                self.receive_until(deadline, &mut pending, &mut responders)
+                    .await?;
+            }
+
+            Ok(responders)
+        }
+    }
+}
+
+/// ARP sweeps need Linux packet sockets.
+#[cfg(not(target_os = "linux"))]
+pub(crate) struct ArpSocket;
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn packet_socket_permitted() -> bool {
+    false
+}
+
+#[cfg(not(target_os = "linux"))]
+impl ArpSocket {
+    pub(crate) fn open(_interface: &str, _source: Ipv4Addr) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "ARP discovery is only supported on Linux",
+        ))
+    }
+
+    pub(crate) async fn sweep(
+        &self,
+        _targets: &[Ipv4Addr],
+        _timeout: Duration,
+        _count: usize,
+        _interval: Duration,
+        _throttle: &Throttle,
+    ) -> io::Result<BTreeMap<Ipv4Addr, ArpResponder>> {
+        Ok(BTreeMap::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::{parse_reply, request_frame};
+    use crate::MacAddr;
+
+    #[test]
+    fn arp_requests_are_broadcast_who_has_frames() {
+        let mac = MacAddr::new([0x02, 0, 0, 0, 0, 1]);
+        let frame = request_frame(
+            mac,
+            Ipv4Addr::new(192, 168, 1, 2),
+            Ipv4Addr::new(192, 168, 1, 77),
+        );
+
+        assert_eq!(&frame[0..6], &[0xff; 6]);
+        assert_eq!(&frame[12..14], &[0x08, 0x06]);
+        assert_eq!(&frame[20..22], &[0, 1]);
+        assert_eq!(&frame[28..32], &[192, 168, 1, 2]);
+        assert_eq!(&frame[38..42], &[192, 168, 1, 77]);
+    }
+
+    #[test]
+    fn arp_replies_yield_the_sender_address() {
+        let sender = MacAddr::new([0xb8, 0x27, 0xeb, 0x12, 0x34, 0x56]);
+        let mut frame = request_frame(
+            sender,
+            Ipv4Addr::new(192, 168, 1, 77),
+            Ipv4Addr::new(192, 168, 1, 2),
+        );
+        frame[20..22].copy_from_slice(&2u16.to_be_bytes());
+
+        assert_eq!(
+            parse_reply(&frame),
+            Some((Ipv4Addr::new(192, 168, 1, 77), sender))
+        );
+    }
+
+    #[test]
+    fn arp_requests_and_short_frames_are_not_replies() {
+        let frame = request_frame(
+            MacAddr::new([0x02, 0, 0, 0, 0, 1]),
+            Ipv4Addr::new(192, 168, 1, 2),
+            Ipv4Addr::new(192, 168, 1, 77),
+        );
+
+        assert_eq!(parse_reply(&frame), None);
+        assert_eq!(parse_reply(&frame[..20]), None);
+    }
+}
@@ -0,0 +1,230 @@
+//! Finding out which probe mechanisms this process can actually use, and
+//! choosing the ICMP backend of a scan from them.
+//!
+//! Each mechanism is tried rather than inferred from the platform or user ID:
+//! a container may run as root without `CAP_NET_RAW`, and an unprivileged
+//! user may be allowed ICMP datagram sockets.
+
+use socket2::Type;
+
+use super::{
+    PingBackend, RuntimePlatform, arp, check_datagram_socket, check_raw_socket, command_exists,
+    current_runtime_platform, icmp,
+};
+use crate::{Error, ScanOptions};
+
+/// The probe mechanisms this process can use.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Capabilities {
+    /// Raw ICMP sockets can be opened, for [`PingBackend::RawSocket`].
+    pub raw_icmp: bool,
+    /// Unprivileged ICMP datagram sockets can be opened, for
+    /// [`PingBackend::Datagram`].
+    pub datagram_icmp: bool,
+    /// The system `ping` command is in `$PATH`, for [`PingBackend::System`].
+    pub system_ping: bool,
+    /// Raw packet sockets can be opened, for [`PingBackend::Arp`].
+    pub raw_packet: bool,
+}
+
+impl Capabilities {
+    /// Try each mechanism. Sockets are opened and closed again without
+    /// sending anything.
+    pub fn probe() -> Self {
+        let icmp_socket = |socket_type| {
+            current_runtime_platform() == RuntimePlatform::Unix
+                && icmp::open_socket(false, None, socket_type).is_ok()
+        };
+        Self {
+            raw_icmp: icmp_socket(Type::RAW),
+            datagram_icmp: icmp_socket(Type::DGRAM),
+            system_ping: command_exists("ping"),
+            raw_packet: arp::packet_socket_permitted(),
+        }
+    }
+
+    /// Whether `backend` can be used. TCP and UDP probes need no privileges.
+    pub fn supports(&self, backend: PingBackend) -> bool {
+        match backend {
+            PingBackend::System => self.system_ping,
+            PingBackend::RawSocket => self.raw_icmp,
+            PingBackend::Datagram => self.datagram_icmp,
+            PingBackend::Arp => self.raw_packet,
+            PingBackend::Tcp | PingBackend::Udp => true,
+        }
+    }
+}
+
+/// How the ICMP backend of a scan is chosen: the backends it may use, best
+/// first, and the first of them that works.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BackendSelection {
+    capabilities: Capabilities,
+    chain: Vec<PingBackend>,
+    chosen: Option<PingBackend>,
+}
+
+impl BackendSelection {
+    /// Probe the capabilities of this process and choose the backend for a
+    /// scan with `options`.
+    pub fn new(options: &ScanOptions) -> Self {
+        Self::with_capabilities(Capabilities::probe(), options)
+    }
+
+    /// Choose the backend for a scan with `options` from capabilities that
+    /// were already probed.
+    pub fn with_capabilities(capabilities: Capabilities, options: &ScanOptions) -> Self {
+        Self::choose(
+            capabilities,
+            backend_chain(
+                current_runtime_platform(),
+                options.method,
+                options.raw_socket,
+            ),
+        )
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// The backends tried, best first. Never empty: an explicit
+    /// [`ScanOptions::method`] or [`ScanOptions::raw_socket`] leaves a single
+    /// backend.
+    pub fn chain(&self) -> &[PingBackend] {
+        &self.chain
+    }
+
+    /// The first backend of the chain that works, or `None` when none does.
+    pub fn chosen(&self) -> Option<PingBackend> {
+        self.chosen
+    }
+
+    /// The chosen backend, or the best of the chain when none works.
+    pub(crate) fn preferred(&self) -> PingBackend {
+        self.chosen.unwrap_or(self.chain[0])
+    }
+
+    fn choose(capabilities: Capabilities, chain: Vec<PingBackend>) -> Self {
+        let chosen = chain
+            .iter()
+            .copied()
+            .find(|backend| capabilities.supports(*backend));
+        Self {
+            capabilities,
+            chain,
+            chosen,
+        }
+    }
+
+    /// The chosen backend, or why none of the chain works.
+    ///
+    /// A single backend that was asked for explicitly fails with the reason it
+    /// is unavailable, such as [`Error::RawSocketPermission`]; an automatic
+    /// chain fails with [`Error::NoUsableBackend`].
+    pub fn backend(&self) -> Result<PingBackend, Error> {
+        if let Some(backend) = self.chosen {
+            return Ok(backend);
+        }
+        match self.chain[..] {
+            [PingBackend::System] => Err(Error::PingNotFound),
+            [PingBackend::RawSocket] => check_raw_socket().map(|()| PingBackend::RawSocket),
+            [PingBackend::Datagram] => check_datagram_socket().map(|()| PingBackend::Datagram),
+            _ => Err(Error::NoUsableBackend(self.chain.clone())),
+        }
+    }
+}
+
+/// The ICMP backends to try, best first.
+///
+/// Datagram sockets come first since they need no privileges and share one
+/// socket across the sweep, like raw sockets. The system `ping` command,
+/// which spawns a process per probe, is the last resort.
+fn backend_chain(
+    platform: RuntimePlatform,
+    method: Option<PingBackend>,
+    raw_socket: bool,
+) -> Vec<PingBackend> {
+    match method {
+        Some(backend @ (PingBackend::System | PingBackend::RawSocket | PingBackend::Datagram)) => {
+            vec![backend]
+        }
+        _ if raw_socket => vec![PingBackend::RawSocket],
+        _ => match platform {
+            RuntimePlatform::Unix => vec![
+                PingBackend::Datagram,
+                PingBackend::RawSocket,
+                PingBackend::System,
+            ],
+            RuntimePlatform::NonUnix => vec![PingBackend::System],
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BackendSelection, Capabilities, backend_chain};
+    use crate::Error;
+    use crate::util::{PingBackend, RuntimePlatform};
+
+    #[test]
+    fn automatic_chains_prefer_sockets_to_the_ping_command() {
+        assert_eq!(
+            backend_chain(RuntimePlatform::Unix, None, false),
+            [
+                PingBackend::Datagram,
+                PingBackend::RawSocket,
+                PingBackend::System
+            ]
+        );
+        assert_eq!(
+            backend_chain(RuntimePlatform::Unix, Some(PingBackend::Tcp), false),
+            backend_chain(RuntimePlatform::Unix, None, false)
+        );
+        assert_eq!(
+            backend_chain(RuntimePlatform::NonUnix, None, false),
+            [PingBackend::System]
+        );
+    }
+
+    #[test]
+    fn explicit_backends_do_not_fall_back() {
+        assert_eq!(
+            backend_chain(RuntimePlatform::Unix, None, true),
+            [PingBackend::RawSocket]
+        );
+        assert_eq!(
+            backend_chain(RuntimePlatform::NonUnix, Some(PingBackend::Datagram), false),
+            [PingBackend::Datagram]
+        );
+
+        let selection = BackendSelection::choose(
+            Capabilities {
+                raw_icmp: true,
+                ..Capabilities::default()
+            },
+            vec![PingBackend::System],
+        );
+        assert_eq!(selection.chosen(), None);
+        assert!(matches!(selection.backend(), Err(Error::PingNotFound)));
+    }
+
+    #[test]
+    fn the_first_working_backend_is_chosen() {
+        let chain = backend_chain(RuntimePlatform::Unix, None, false);
+        let capabilities = Capabilities {
+            raw_icmp: true,
+            system_ping: true,
+            ..Capabilities::default()
+        };
+
+        let selection = BackendSelection::choose(capabilities, chain.clone());
+        assert_eq!(selection.backend().ok(), Some(PingBackend::RawSocket));
+
+        let selection = BackendSelection::choose(Capabilities::default(), chain.clone());
+        assert!(matches!(
+            selection.backend(),
+            Err(Error::NoUsableBackend(tried)) if tried == chain
+        ));
+    }
+}
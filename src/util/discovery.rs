@@ -0,0 +1,259 @@
+//! Service discovery probes: SSDP, multicast DNS and NetBIOS broadcasts.
+//!
+//! Some devices ignore ping but answer the discovery traffic their own
+//! ecosystem relies on. Each probe is one query sent to a multicast or
+//! broadcast address of an interface; every address that answers before the
+//! timeout is a host.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tokio::time::Instant;
+
+use super::dns::{RecordType, query};
+use super::dnssd::SERVICES_NAME;
+use super::mdns::{MDNS_IPV4, MDNS_IPV6, MDNS_PORT, ipv4_socket, ipv6_socket};
+use super::netbios::{NETBIOS_NAME_PORT, nbstat_query, parse_nbstat_response};
+use super::{DiscoveredAddress, InterfaceAddress};
+use crate::Probe;
+
+const SSDP_PORT: u16 = 1900;
+const SSDP_IPV4: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const SSDP_IPV6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xc);
+/// Hops SSDP multicast may travel, as the UPnP Device Architecture recommends.
+const SSDP_TTL: u32 = 2;
+/// Query ID of NetBIOS broadcasts. Answers are told apart by their source.
+const NETBIOS_QUERY_ID: u16 = 0x7069;
+
+/// Tells answers to a probe apart from unrelated packets.
+type Accept = fn(&[u8]) -> bool;
+
+/// An `M-SEARCH` request for every device and service, sent to `host`.
+fn ssdp_search(host: &str) -> Vec<u8> {
+    format!(
+        "M-SEARCH * HTTP/1.1\r\n\
+         HOST: {}:{}\r\n\
+         MAN: \"ssdp:discover\"\r\n\
+         MX: 1\r\n\
+         ST: ssdp:all\r\n\
+         \r\n",
+        host, SSDP_PORT
+    )
+    .into_bytes()
+}
+
+fn is_ssdp_response(packet: &[u8]) -> bool {
+    packet.starts_with(b"HTTP/1.1 200") || packet.starts_with(b"HTTP/1.0 200")
+}
+
+fn is_mdns_response(packet: &[u8]) -> bool {
+    packet.len() >= 12 && packet[2] & 0x80 != 0
+}
+
+fn is_nbstat_response(packet: &[u8]) -> bool {
+    parse_nbstat_response(NETBIOS_QUERY_ID, packet).is_some()
+}
+
+/// The broadcast address of an IPv4 subnet.
+fn broadcast_address(ip: Ipv4Addr, prefix_len: u8) -> Ipv4Addr {
+    let host_mask = u32::MAX.checked_shr(u32::from(prefix_len)).unwrap_or(0);
+    Ipv4Addr::from(u32::from(ip) | host_mask)
+}
+
+/// Send every discovery probe that applies to `address` and wait up to
+/// `timeout` for answers.
+///
+/// IPv4 addresses get SSDP, multicast DNS and a NetBIOS broadcast; IPv6
+/// addresses get SSDP and multicast DNS on their link. Probes whose socket
+/// cannot be opened are skipped.
+pub(crate) async fn discover(
+    address: &InterfaceAddress,
+    timeout: Duration,
+) -> Vec<(Probe, DiscoveredAddress)> {
+    let (probes, interface) = match address {
+        InterfaceAddress::V4 {
+            ip,
+            prefix_len,
+            interface,
+        } => {
+            let ssdp = SocketAddr::from((SSDP_IPV4, SSDP_PORT));
+            let mdns = SocketAddr::from((MDNS_IPV4, MDNS_PORT));
+            let netbios =
+                SocketAddr::from((broadcast_address(*ip, *prefix_len), NETBIOS_NAME_PORT));
+            let probes = vec![
+                (Probe::Ssdp, ipv4_socket(*ip), ssdp),
+                (Probe::Mdns, ipv4_socket(*ip), mdns),
+                (Probe::Netbios, ipv4_socket(*ip), netbios),
+            ];
+            (probes, interface)
+        }
+        InterfaceAddress::V6 {
+            interface,
+            index: Some(index),
+            ..
+        } => {
+            let ssdp = SocketAddrV6::new(SSDP_IPV6, SSDP_PORT, 0, *index);
+            let mdns = SocketAddrV6::new(MDNS_IPV6, MDNS_PORT, 0, *index);
+            let probes = vec![
+                (Probe::Ssdp, ipv6_socket(*index), SocketAddr::V6(ssdp)),
+                (Probe::Mdns, ipv6_socket(*index), SocketAddr::V6(mdns)),
+            ];
+            (probes, interface)
+        }
+        InterfaceAddress::V6 { index: None, .. } => return Vec::new(),
+    };
+
+    let mut tasks = tokio::task::JoinSet::new();
+    for (probe, socket, destination) in probes {
+        let Ok(socket) = socket else {
+            continue;
+        };
+        let (packet, accept): (Vec<u8>, Accept) = match probe {
+            Probe::Ssdp => {
+                let host = match destination {
+                    SocketAddr::V4(_) => {
+                        let _ = socket.set_multicast_ttl_v4(SSDP_TTL);
+                        SSDP_IPV4.to_string()
+                    }
+                    SocketAddr::V6(_) => format!("[{}]", SSDP_IPV6),
+                };
+                (ssdp_search(&host), is_ssdp_response)
+            }
+            Probe::Mdns => (
+                query(0, 0, SERVICES_NAME, RecordType::Ptr),
+                is_mdns_response,
+            ),
+            Probe::Netbios => {
+                if socket.set_broadcast(true).is_err() {
+                    continue;
+                }
+                (nbstat_query(NETBIOS_QUERY_ID), is_nbstat_response)
+            }
+            Probe::Sweep | Probe::Ipv6Multicast => continue,
+        };
+        tasks.spawn(async move {
+            let responders = collect(&socket, &packet, destination, timeout, accept).await;
+            (probe, responders)
+        });
+    }
+
+    let mut found = Vec::new();
+    while let Some(Ok((probe, responders))) = tasks.join_next().await {
+        found.extend(responders.into_iter().map(|(ip_addr, rtt)| {
+            let address = DiscoveredAddress {
+                ip_addr,
+                scope: Some(interface.clone()),
+                rtt: Some(rtt),
+            };
+            (probe, address)
+        }));
+    }
+    found
+}
+
+/// Send `packet` to `destination` and gather the addresses that answer with
+/// an accepted packet, with how long the first answer took.
+async fn collect(
+    socket: &UdpSocket,
+    packet: &[u8],
+    destination: SocketAddr,
+    timeout: Duration,
+    accept: Accept,
+) -> BTreeMap<IpAddr, Duration> {
+    let mut responders = BTreeMap::new();
+    let start = Instant::now();
+    if socket.send_to(packet, destination).await.is_err() {
+        return responders;
+    }
+
+    let mut buffer = [0u8; 9000];
+    let deadline = start + timeout;
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer)).await
+    {
+        let (received, peer) = match received {
+            Ok(received) => received,
+            // Windows reports ICMP port unreachable errors on later reads.
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+            Err(_) => break,
+        };
+        if accept(&buffer[..received]) {
+            responders
+                .entry(peer.ip())
+                .or_insert_with(|| start.elapsed());
+        }
+    }
+
+    responders
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+
+    use tokio::net::UdpSocket;
+
+    use super::{broadcast_address, collect, is_ssdp_response, ssdp_search};
+
+    #[test]
+    fn broadcast_addresses_fill_the_host_bits() {
+        let ip = Ipv4Addr::new(192, 168, 1, 20);
+
+        assert_eq!(broadcast_address(ip, 24), Ipv4Addr::new(192, 168, 1, 255));
+        assert_eq!(broadcast_address(ip, 20), Ipv4Addr::new(192, 168, 15, 255));
+        assert_eq!(broadcast_address(ip, 32), ip);
+        assert_eq!(broadcast_address(ip, 0), Ipv4Addr::BROADCAST);
+    }
+
+    #[test]
+    fn ssdp_searches_ask_for_everything() {
+        let search = String::from_utf8(ssdp_search("239.255.255.250")).unwrap();
+
+        assert!(search.starts_with("M-SEARCH * HTTP/1.1\r\n"));
+        assert!(search.contains("HOST: 239.255.255.250:1900\r\n"));
+        assert!(search.contains("ST: ssdp:all\r\n"));
+        assert!(search.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn responders_are_collected_once_each() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let device = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+                let destination = device.local_addr().unwrap();
+                tokio::spawn(async move {
+                    let mut buffer = [0u8; 512];
+                    let (_, peer) = device.recv_from(&mut buffer).await.unwrap();
+                    device
+                        .send_to(b"NOTIFY * HTTP/1.1\r\n\r\n", peer)
+                        .await
+                        .unwrap();
+                    for _ in 0..2 {
+                        let response = b"HTTP/1.1 200 OK\r\nST: upnp:rootdevice\r\n\r\n";
+                        device.send_to(response, peer).await.unwrap();
+                    }
+                });
+
+                let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+                let responders = collect(
+                    &socket,
+                    &ssdp_search("239.255.255.250"),
+                    destination,
+                    Duration::from_millis(300),
+                    is_ssdp_response,
+                )
+                .await;
+
+                assert_eq!(
+                    responders.keys().copied().collect::<Vec<_>>(),
+                    vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]
+                );
+            });
+    }
+}
@@ -0,0 +1,566 @@
+//! Reverse DNS (PTR) lookups, and the address lookups that confirm them, over UDP.
+//!
+//! Queries go straight to the configured name servers, so hostnames resolve
+//! on machines without avahi or a system resolver library.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+const DNS_PORT: u16 = 53;
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const HEADER_LEN: usize = 12;
+/// How long each server gets to answer a query.
+pub(crate) const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+/// Compression pointers followed before a name is considered malformed.
+const MAX_POINTERS: usize = 16;
+
+/// The `in-addr.arpa` or `ip6.arpa` name that holds the PTR record of `ip_addr`.
+pub(crate) fn ptr_name(ip_addr: IpAddr) -> String {
+    match ip_addr {
+        IpAddr::V4(ip_addr) => {
+            let [a, b, c, d] = ip_addr.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+        }
+        IpAddr::V6(ip_addr) => {
+            let mut name = String::with_capacity(72);
+            for octet in ip_addr.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", octet & 0xf, octet >> 4));
+            }
+            name.push_str("ip6.arpa");
+            name
+        }
+    }
+}
+
+/// The kinds of record `pingall` asks for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum RecordType {
+    Ptr,
+    /// `A` or `AAAA`, whichever matches the family of the address being confirmed.
+    Address {
+        ipv6: bool,
+    },
+    /// Every record of the name, such as the SRV and TXT records of a service.
+    Any,
+}
+
+impl RecordType {
+    fn code(self) -> u16 {
+        match self {
+            RecordType::Ptr => TYPE_PTR,
+            RecordType::Address { ipv6: false } => TYPE_A,
+            RecordType::Address { ipv6: true } => TYPE_AAAA,
+            RecordType::Any => TYPE_ANY,
+        }
+    }
+}
+
+/// The data of a record `pingall` understands.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum RecordData {
+    Ptr(String),
+    Address(IpAddr),
+    Srv {
+        port: u16,
+        target: String,
+    },
+    /// The character strings of a TXT record, e.g. `rp=ipp/print`.
+    Txt(Vec<String>),
+}
+
+/// Build a query for the `record_type` record of `name` with the given header flags.
+pub(crate) fn query(id: u16, flags: u16, name: &str, record_type: RecordType) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(HEADER_LEN + name.len() + 6);
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&flags.to_be_bytes());
+    packet.extend_from_slice(&1u16.to_be_bytes());
+    packet.extend_from_slice(&[0; 6]);
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&record_type.code().to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    packet
+}
+
+/// Read a possibly compressed name starting at `offset`.
+///
+/// Returns the name and the offset just past it in the original record.
+fn read_name(packet: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    for _ in 0..MAX_POINTERS {
+        loop {
+            let len = *packet.get(offset)? as usize;
+            match len {
+                0 => {
+                    let name = labels.join(".");
+                    return Some((name, end.unwrap_or(offset + 1)));
+                }
+                len if len & 0xc0 == 0xc0 => {
+                    let pointer = u16::from_be_bytes([len as u8, *packet.get(offset + 1)?]);
+                    end.get_or_insert(offset + 2);
+                    offset = usize::from(pointer & 0x3fff);
+                    break;
+                }
+                len => {
+                    let label = packet.get(offset + 1..offset + 1 + len)?;
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    offset += 1 + len;
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// The length-prefixed character strings of a TXT record, without the empty
+/// string that stands for no data.
+fn read_strings(mut data: &[u8]) -> Vec<String> {
+    let mut strings = Vec::new();
+    while let [len, rest @ ..] = data {
+        let len = usize::from(*len).min(rest.len());
+        if len > 0 {
+            strings.push(String::from_utf8_lossy(&rest[..len]).into_owned());
+        }
+        data = &rest[len..];
+    }
+    strings
+}
+
+fn read_u16(packet: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([
+        *packet.get(offset)?,
+        *packet.get(offset + 1)?,
+    ]))
+}
+
+/// Extract the records of a response to the query with `id`.
+///
+/// Returns `None` for responses to other queries, errors and malformed packets.
+pub(crate) fn parse_response(id: u16, packet: &[u8]) -> Option<Vec<RecordData>> {
+    let flags = read_u16(packet, 2)?;
+    if read_u16(packet, 0)? != id || flags & 0x000f != 0 {
+        return None;
+    }
+
+    let records: Vec<_> = records(packet).into_iter().map(|(_, data)| data).collect();
+    (!records.is_empty()).then_some(records)
+}
+
+/// Owner names and data of the PTR, A, AAAA, SRV and TXT records in any
+/// section of a response.
+///
+/// Records after a malformed one are ignored.
+pub(crate) fn records(packet: &[u8]) -> Vec<(String, RecordData)> {
+    let mut records = Vec::new();
+    let header = |offset| read_u16(packet, offset).unwrap_or_default();
+    if header(2) & FLAG_RESPONSE == 0 {
+        return records;
+    }
+
+    let mut offset = HEADER_LEN;
+    for _ in 0..header(4) {
+        match read_name(packet, offset) {
+            Some((_, end)) => offset = end + 4,
+            None => return records,
+        }
+    }
+    for _ in 0..u32::from(header(6)) + u32::from(header(8)) + u32::from(header(10)) {
+        let Some((name, end)) = read_name(packet, offset) else {
+            break;
+        };
+        let (Some(record_type), Some(data_len)) =
+            (read_u16(packet, end), read_u16(packet, end + 8))
+        else {
+            break;
+        };
+        let data = end + 10;
+        let record = match (record_type, packet.get(data..data + usize::from(data_len))) {
+            (TYPE_PTR, Some(_)) => {
+                read_name(packet, data).map(|(target, _)| RecordData::Ptr(target))
+            }
+            (TYPE_A, Some(&[a, b, c, d])) => Some(RecordData::Address(IpAddr::from([a, b, c, d]))),
+            (TYPE_AAAA, Some(octets)) => <[u8; 16]>::try_from(octets)
+                .ok()
+                .map(|octets| RecordData::Address(IpAddr::from(octets))),
+            (TYPE_SRV, Some(_)) => {
+                let port = read_u16(packet, data + 4);
+                let target = read_name(packet, data + 6);
+                port.zip(target)
+                    .map(|(port, (target, _))| RecordData::Srv { port, target })
+            }
+            (TYPE_TXT, Some(strings)) => Some(RecordData::Txt(read_strings(strings))),
+            _ => None,
+        };
+        records.extend(record.map(|record| (name, record)));
+        offset = data + usize::from(data_len);
+    }
+
+    records
+}
+
+/// Name servers listed in a `resolv.conf` file.
+pub(crate) fn parse_resolv_conf(contents: &str) -> Vec<SocketAddr> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            if fields.next()? != "nameserver" {
+                return None;
+            }
+            // Zone IDs of link-local servers are not supported.
+            let address = fields.next()?.split('%').next()?;
+            let ip_addr = address.parse().ok()?;
+            Some(SocketAddr::new(ip_addr, DNS_PORT))
+        })
+        .collect()
+}
+
+/// Name servers from `/etc/resolv.conf`.
+pub(crate) fn system_name_servers() -> Vec<SocketAddr> {
+    std::fs::read_to_string("/etc/resolv.conf")
+        .map(|contents| parse_resolv_conf(&contents))
+        .unwrap_or_default()
+}
+
+/// Ask each server in turn for the PTR records of `ip_addr`, stopping at the
+/// first one that answers.
+pub(crate) async fn resolve_ptr(
+    ip_addr: IpAddr,
+    servers: &[SocketAddr],
+    timeout: Duration,
+) -> Vec<String> {
+    let records = exchange(
+        &ptr_name(ip_addr),
+        RecordType::Ptr,
+        ip_addr,
+        servers,
+        timeout,
+    )
+    .await;
+    records
+        .into_iter()
+        .flatten()
+        .filter_map(|record| match record {
+            RecordData::Ptr(hostname) => Some(hostname),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Ask each server in turn for the addresses of `name` in the family of
+/// `ip_addr`. Returns `None` when no server answers.
+pub(crate) async fn resolve_addresses(
+    name: &str,
+    ip_addr: IpAddr,
+    servers: &[SocketAddr],
+    timeout: Duration,
+) -> Option<Vec<IpAddr>> {
+    let record_type = RecordType::Address {
+        ipv6: ip_addr.is_ipv6(),
+    };
+    let records = exchange(name, record_type, ip_addr, servers, timeout).await?;
+    Some(
+        records
+            .into_iter()
+            .filter_map(|record| match record {
+                RecordData::Address(ip_addr) => Some(ip_addr),
+                _ => None,
+            })
+            .collect(),
+    )
+}
+
+/// Send a recursive query to each server in turn and return the records of
+/// the first response, which is empty when the name does not exist.
+async fn exchange(
+    name: &str,
+    record_type: RecordType,
+    ip_addr: IpAddr,
+    servers: &[SocketAddr],
+    timeout: Duration,
+) -> Option<Vec<RecordData>> {
+    for server in servers {
+        let id = query_id(ip_addr, *server);
+        let bind = match server {
+            SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+            SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+        };
+        let Ok(socket) = UdpSocket::bind(bind).await else {
+            continue;
+        };
+        if socket.connect(server).await.is_err()
+            || socket
+                .send(&query(id, FLAG_RECURSION_DESIRED, name, record_type))
+                .await
+                .is_err()
+        {
+            continue;
+        }
+
+        let mut buffer = [0u8; 1500];
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let received = match tokio::time::timeout_at(deadline, socket.recv(&mut buffer)).await {
+                Ok(Ok(received)) => received,
+                _ => break,
+            };
+            // A response from this server means the answer is final, even if it is empty.
+            if received >= HEADER_LEN
+                && buffer[..2] == id.to_be_bytes()
+                && u16::from_be_bytes([buffer[2], buffer[3]]) & FLAG_RESPONSE != 0
+            {
+                return Some(parse_response(id, &buffer[..received]).unwrap_or_default());
+            }
+        }
+    }
+
+    None
+}
+
+/// A query ID that differs between hosts and servers, without a random number generator.
+pub(crate) fn query_id(ip_addr: IpAddr, server: SocketAddr) -> u16 {
+    use std::hash::{BuildHasher, RandomState};
+
+    RandomState::new().hash_one((ip_addr, server)) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::time::Duration;
+
+    use tokio::net::UdpSocket;
+
+    use super::{
+        FLAG_RECURSION_DESIRED, RecordData, RecordType, parse_resolv_conf, parse_response,
+        ptr_name, query, records, resolve_addresses, resolve_ptr,
+    };
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    fn ptr_query(id: u16, name: &str) -> Vec<u8> {
+        query(id, FLAG_RECURSION_DESIRED, name, RecordType::Ptr)
+    }
+
+    /// Answer a query with a PTR record pointing at `hostname`, compressing
+    /// the owner name like real servers do.
+    fn ptr_answer(query: &[u8], hostname: &str) -> Vec<u8> {
+        let mut response = query.to_vec();
+        response[2] |= 0x80;
+        response[7] = 1;
+        response.extend_from_slice(&[0xc0, 12, 0, 12, 0, 1, 0, 0, 0x0e, 0x10]);
+        let mut data = Vec::new();
+        for label in hostname.split('.') {
+            data.push(label.len() as u8);
+            data.extend_from_slice(label.as_bytes());
+        }
+        data.push(0);
+        response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        response.extend_from_slice(&data);
+        response
+    }
+
+    #[test]
+    fn ptr_names_reverse_the_address() {
+        assert_eq!(
+            ptr_name("192.168.1.10".parse().unwrap()),
+            "10.1.168.192.in-addr.arpa"
+        );
+        assert_eq!(
+            ptr_name("2001:db8::567:89ab".parse().unwrap()),
+            "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+    }
+
+    #[test]
+    fn ptr_responses_yield_the_target_name() {
+        let query = ptr_query(0x1234, "10.1.168.192.in-addr.arpa");
+        let response = ptr_answer(&query, "printer.example.com");
+
+        assert_eq!(
+            parse_response(0x1234, &response),
+            Some(vec![RecordData::Ptr("printer.example.com".to_string())])
+        );
+        assert_eq!(parse_response(0x4321, &response), None);
+        assert_eq!(parse_response(0x1234, &query), None);
+        assert_eq!(parse_response(0x1234, &response[..20]), None);
+    }
+
+    #[test]
+    fn address_records_are_read_by_family() {
+        let query = query(
+            7,
+            FLAG_RECURSION_DESIRED,
+            "nas.example.com",
+            RecordType::Address { ipv6: false },
+        );
+        let mut response = query.clone();
+        response[2] |= 0x80;
+        response[7] = 3;
+        let owner = [0xc0, 12];
+        response.extend_from_slice(&owner);
+        response.extend_from_slice(&[0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 168, 1, 10]);
+        response.extend_from_slice(&owner);
+        response.extend_from_slice(&[0, 28, 0, 1, 0, 0, 0, 60, 0, 16]);
+        response.extend_from_slice(
+            &"2001:db8::10"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets(),
+        );
+        // A truncated A record is skipped rather than misread.
+        response.extend_from_slice(&owner);
+        response.extend_from_slice(&[0, 1, 0, 1, 0, 0, 0, 60, 0, 3, 10, 0, 0]);
+
+        assert_eq!(
+            records(&response),
+            vec![
+                (
+                    "nas.example.com".to_string(),
+                    RecordData::Address("192.168.1.10".parse().unwrap())
+                ),
+                (
+                    "nas.example.com".to_string(),
+                    RecordData::Address("2001:db8::10".parse().unwrap())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn service_records_carry_port_target_and_txt_strings() {
+        let query = query(0, 0, "Office Printer._ipp._tcp.local", RecordType::Any);
+        let mut response = query.clone();
+        response[2] |= 0x80;
+        response[7] = 2;
+        let owner = [0xc0, 12];
+        response.extend_from_slice(&owner);
+        response.extend_from_slice(&[0, 33, 0x80, 1, 0, 0, 0, 120, 0, 21, 0, 0, 0, 0, 2, 0x77]);
+        response.extend_from_slice(b"\x07printer\x05local\x00");
+        response.extend_from_slice(&owner);
+        response.extend_from_slice(&[0, 16, 0x80, 1, 0, 0, 0, 120, 0, 23]);
+        response.extend_from_slice(b"\x0crp=ipp/print\x00\x08ty=Laser");
+
+        assert_eq!(
+            records(&response),
+            vec![
+                (
+                    "Office Printer._ipp._tcp.local".to_string(),
+                    RecordData::Srv {
+                        port: 631,
+                        target: "printer.local".to_string()
+                    }
+                ),
+                (
+                    "Office Printer._ipp._tcp.local".to_string(),
+                    RecordData::Txt(vec!["rp=ipp/print".to_string(), "ty=Laser".to_string()])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn ptr_records_are_read_from_every_section() {
+        let query = ptr_query(0, "20.1.168.192.in-addr.arpa");
+        let mut response = ptr_answer(&query, "nas.local");
+        // Move the answer to the additional section, as mDNS responders may.
+        response[7] = 0;
+        response[11] = 1;
+
+        assert_eq!(
+            records(&response),
+            vec![(
+                "20.1.168.192.in-addr.arpa".to_string(),
+                RecordData::Ptr("nas.local".to_string())
+            )]
+        );
+        assert_eq!(records(&query), vec![]);
+    }
+
+    #[test]
+    fn resolv_conf_lists_name_servers() {
+        let servers = parse_resolv_conf(
+            "# generated\nsearch example.com\nnameserver 10.0.0.53\nnameserver fe80::1%eth0\nnameserver 2001:db8::53\n",
+        );
+
+        assert_eq!(
+            servers,
+            vec![
+                "10.0.0.53:53".parse::<SocketAddr>().unwrap(),
+                "[fe80::1]:53".parse().unwrap(),
+                "[2001:db8::53]:53".parse().unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn lookups_query_a_stub_server() {
+        runtime().block_on(async {
+            let stub = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let server = stub.local_addr().unwrap();
+            tokio::spawn(async move {
+                let mut buffer = [0u8; 512];
+                let (received, peer) = stub.recv_from(&mut buffer).await.unwrap();
+                let response = ptr_answer(&buffer[..received], "nas.lan");
+                stub.send_to(&response, peer).await.unwrap();
+            });
+
+            let hostname = resolve_ptr(
+                IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20)),
+                &[server],
+                Duration::from_secs(2),
+            )
+            .await;
+
+            assert_eq!(hostname, vec!["nas.lan".to_string()]);
+        });
+    }
+
+    #[test]
+    fn address_lookups_tell_no_answer_from_no_addresses() {
+        runtime().block_on(async {
+            let stub = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let server = stub.local_addr().unwrap();
+            tokio::spawn(async move {
+                let mut buffer = [0u8; 512];
+                let (received, peer) = stub.recv_from(&mut buffer).await.unwrap();
+                // NXDOMAIN
+                let mut response = buffer[..received].to_vec();
+                response[2] |= 0x80;
+                response[3] |= 3;
+                stub.send_to(&response, peer).await.unwrap();
+            });
+            let ip_addr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+            let timeout = Duration::from_millis(200);
+
+            assert_eq!(
+                resolve_addresses("gone.lan", ip_addr, &[server], timeout).await,
+                Some(vec![])
+            );
+            assert_eq!(
+                resolve_addresses("gone.lan", ip_addr, &[server], timeout).await,
+                None
+            );
+        });
+    }
+}
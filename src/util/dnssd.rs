@@ -0,0 +1,309 @@
+//! DNS-SD service browsing over multicast DNS (RFC 6763).
+//!
+//! Browsing takes up to three rounds of legacy unicast queries on each
+//! interface: one for the service types on the link, one for the instances of
+//! each type, and one for the SRV and TXT records of instances whose
+//! responders did not volunteer them. Each round waits the full timeout,
+//! since any number of hosts may answer.
+
+use std::io;
+use std::net::{IpAddr, SocketAddr, SocketAddrV6};
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+use super::InterfaceAddress;
+use super::dns::{RecordData, RecordType, query, records};
+use super::mdns::{MDNS_IPV4, MDNS_IPV6, MDNS_PORT, ipv4_socket, ipv6_socket};
+use crate::Service;
+
+/// The DNS-SD meta-query that lists every service type on the link.
+pub(super) const SERVICES_NAME: &str = "_services._dns-sd._udp.local";
+
+/// A record and the address of the responder that sent it.
+type Answer = (IpAddr, String, RecordData);
+
+/// Browse the services advertised on the link of `address`, waiting up to
+/// `timeout` for each round of answers.
+///
+/// Each service is paired with the address of the host offering it: the
+/// responder that sent its SRV record, and any addresses of the same family
+/// it gave for the SRV target.
+pub(crate) async fn browse(
+    address: &InterfaceAddress,
+    timeout: Duration,
+) -> Vec<(IpAddr, Service)> {
+    let (socket, destination) = match address {
+        InterfaceAddress::V4 { ip, .. } => {
+            (ipv4_socket(*ip), SocketAddr::from((MDNS_IPV4, MDNS_PORT)))
+        }
+        InterfaceAddress::V6 {
+            index: Some(index), ..
+        } => (
+            ipv6_socket(*index),
+            SocketAddr::V6(SocketAddrV6::new(MDNS_IPV6, MDNS_PORT, 0, *index)),
+        ),
+        InterfaceAddress::V6 { index: None, .. } => return Vec::new(),
+    };
+    let Ok(socket) = socket else {
+        return Vec::new();
+    };
+
+    let mut answers = Vec::new();
+    let meta = [SERVICES_NAME.to_string()];
+    ask(
+        &socket,
+        &meta,
+        RecordType::Ptr,
+        destination,
+        timeout,
+        &mut answers,
+    )
+    .await;
+    let types = pointers(&answers, &meta);
+    ask(
+        &socket,
+        &types,
+        RecordType::Ptr,
+        destination,
+        timeout,
+        &mut answers,
+    )
+    .await;
+    let missing: Vec<String> = pointers(&answers, &types)
+        .into_iter()
+        .filter(|instance| {
+            !answers.iter().any(|(_, owner, record)| {
+                owner.eq_ignore_ascii_case(instance) && matches!(record, RecordData::Srv { .. })
+            })
+        })
+        .collect();
+    ask(
+        &socket,
+        &missing,
+        RecordType::Any,
+        destination,
+        timeout,
+        &mut answers,
+    )
+    .await;
+
+    assemble(&answers)
+}
+
+/// Send a query for each of `names` and add every record that arrives within
+/// `timeout` to `answers`. Does nothing when `names` is empty.
+async fn ask(
+    socket: &UdpSocket,
+    names: &[String],
+    record_type: RecordType,
+    destination: SocketAddr,
+    timeout: Duration,
+    answers: &mut Vec<Answer>,
+) {
+    if names.is_empty() {
+        return;
+    }
+    for name in names {
+        if socket
+            .send_to(&query(0, 0, name, record_type), destination)
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+
+    let mut buffer = [0u8; 9000];
+    let deadline = tokio::time::Instant::now() + timeout;
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer)).await
+    {
+        let (received, peer) = match received {
+            Ok(received) => received,
+            // Windows reports ICMP port unreachable errors on later reads.
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+            Err(_) => break,
+        };
+        let packet = &buffer[..received];
+        if packet.len() < 12 || packet[2] & 0x80 == 0 {
+            continue;
+        }
+        answers.extend(
+            records(packet)
+                .into_iter()
+                .map(|(owner, record)| (peer.ip(), owner, record)),
+        );
+    }
+}
+
+/// The distinct targets of the PTR records owned by any of `names`.
+fn pointers(answers: &[Answer], names: &[String]) -> Vec<String> {
+    let mut targets: Vec<String> = Vec::new();
+    for (_, owner, record) in answers {
+        if let RecordData::Ptr(target) = record
+            && names.iter().any(|name| name.eq_ignore_ascii_case(owner))
+            && !targets
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(target))
+        {
+            targets.push(target.clone());
+        }
+    }
+    targets
+}
+
+/// Split a service instance name such as `Office Printer._ipp._tcp.local`
+/// into the instance (`Office Printer`) and the service type (`_ipp._tcp`).
+fn split_instance_name(name: &str) -> Option<(&str, &str)> {
+    let (rest, _domain) = name.trim_end_matches('.').rsplit_once('.')?;
+    let (rest, protocol) = rest.rsplit_once('.')?;
+    if protocol != "_tcp" && protocol != "_udp" {
+        return None;
+    }
+    let (instance, application) = rest.rsplit_once('.')?;
+    if !application.starts_with('_') || instance.is_empty() {
+        return None;
+    }
+    let service_type = &name[instance.len() + 1..rest.len() + 1 + protocol.len()];
+    Some((instance, service_type))
+}
+
+/// Build the services described by the SRV records among `answers`, with the
+/// TXT record of each instance and the addresses of the host offering it.
+fn assemble(answers: &[Answer]) -> Vec<(IpAddr, Service)> {
+    let mut services: Vec<(IpAddr, Service)> = Vec::new();
+    for (source, owner, record) in answers {
+        let RecordData::Srv { port, target } = record else {
+            continue;
+        };
+        let Some((instance, service_type)) = split_instance_name(owner) else {
+            continue;
+        };
+        let txt = answers
+            .iter()
+            .find_map(|(_, name, record)| match record {
+                RecordData::Txt(txt) if name.eq_ignore_ascii_case(owner) => Some(txt.clone()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let service = Service {
+            instance: instance.to_string(),
+            service_type: service_type.to_string(),
+            port: *port,
+            txt,
+        };
+
+        let addresses = answers.iter().filter_map(|(_, name, record)| match record {
+            RecordData::Address(ip_addr)
+                if name.eq_ignore_ascii_case(target) && ip_addr.is_ipv4() == source.is_ipv4() =>
+            {
+                Some(*ip_addr)
+            }
+            _ => None,
+        });
+        for ip_addr in std::iter::once(*source).chain(addresses) {
+            let entry = (ip_addr, service.clone());
+            if !services.contains(&entry) {
+                services.push(entry);
+            }
+        }
+    }
+
+    services
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::{Answer, assemble, pointers, split_instance_name};
+    use crate::Service;
+    use crate::util::dns::RecordData;
+
+    fn answer(source: &str, owner: &str, record: RecordData) -> Answer {
+        (source.parse().unwrap(), owner.to_string(), record)
+    }
+
+    #[test]
+    fn instance_names_split_at_the_service_type() {
+        assert_eq!(
+            split_instance_name("Office Printer._ipp._tcp.local"),
+            Some(("Office Printer", "_ipp._tcp"))
+        );
+        assert_eq!(
+            split_instance_name("Kitchen.speaker._raop._tcp.local."),
+            Some(("Kitchen.speaker", "_raop._tcp"))
+        );
+        assert_eq!(split_instance_name("_ipp._tcp.local"), None);
+        assert_eq!(split_instance_name("nas.local"), None);
+    }
+
+    #[test]
+    fn pointers_are_followed_once() {
+        let answers = vec![
+            answer(
+                "192.168.1.20",
+                "_services._dns-sd._udp.local",
+                RecordData::Ptr("_ipp._tcp.local".to_string()),
+            ),
+            answer(
+                "192.168.1.30",
+                "_SERVICES._dns-sd._udp.local",
+                RecordData::Ptr("_IPP._tcp.local".to_string()),
+            ),
+            answer(
+                "192.168.1.30",
+                "_ipp._tcp.local",
+                RecordData::Ptr("Office Printer._ipp._tcp.local".to_string()),
+            ),
+        ];
+
+        assert_eq!(
+            pointers(&answers, &["_services._dns-sd._udp.local".to_string()]),
+            ["_ipp._tcp.local"]
+        );
+    }
+
+    #[test]
+    fn services_are_assembled_per_host() {
+        let instance = "Office Printer._ipp._tcp.local";
+        let srv = RecordData::Srv {
+            port: 631,
+            target: "printer.local".to_string(),
+        };
+        let answers = vec![
+            answer("192.168.1.30", instance, srv.clone()),
+            answer(
+                "192.168.1.30",
+                instance,
+                RecordData::Txt(vec!["rp=ipp/print".to_string(), "ty=Laser".to_string()]),
+            ),
+            answer(
+                "192.168.1.30",
+                "printer.local",
+                RecordData::Address("192.168.1.31".parse().unwrap()),
+            ),
+            answer(
+                "192.168.1.30",
+                "printer.local",
+                RecordData::Address("fe80::31".parse().unwrap()),
+            ),
+            // Repeated answers from a later round add nothing.
+            answer("192.168.1.30", instance, srv),
+        ];
+        let service = Service {
+            instance: "Office Printer".to_string(),
+            service_type: "_ipp._tcp".to_string(),
+            port: 631,
+            txt: vec!["rp=ipp/print".to_string(), "ty=Laser".to_string()],
+        };
+
+        assert_eq!(
+            assemble(&answers),
+            vec![
+                ("192.168.1.30".parse::<IpAddr>().unwrap(), service.clone()),
+                ("192.168.1.31".parse().unwrap(), service),
+            ]
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Hostnames from local files: `/etc/hosts` and DHCP server leases.
+//!
+//! Reads hosts files, dnsmasq `dnsmasq.leases`, ISC `dhcpd.leases` and
+//! systemd-networkd lease files, telling them apart by their contents.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use crate::Error;
+
+/// Files read when they exist, in addition to any given explicitly.
+const DEFAULT_HOSTS_PATHS: &[&str] = &[
+    "/etc/hosts",
+    "/var/lib/misc/dnsmasq.leases",
+    "/var/lib/dnsmasq/dnsmasq.leases",
+    "/var/lib/dhcp/dhcpd.leases",
+    "/var/lib/dhcpd/dhcpd.leases",
+];
+
+/// Directory holding one systemd-networkd lease file per interface.
+const NETWORKD_LEASE_DIR: &str = "/run/systemd/netif/leases";
+
+/// Names keyed by address, in the order the files list them.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct HostsTable {
+    names: HashMap<IpAddr, Vec<String>>,
+}
+
+impl HostsTable {
+    /// Read the default files that exist, then `paths`, which must all be readable.
+    pub(crate) fn load(paths: &[PathBuf]) -> Result<Self, Error> {
+        let mut table = Self::default();
+        let networkd_leases = std::fs::read_dir(NETWORKD_LEASE_DIR)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| Some(entry.ok()?.path()));
+        for path in DEFAULT_HOSTS_PATHS
+            .iter()
+            .map(PathBuf::from)
+            .chain(networkd_leases)
+        {
+            if let Ok(contents) = std::fs::read_to_string(&path) {
+                table.parse(&contents);
+            }
+        }
+        for path in paths {
+            table.parse(&read(path)?);
+        }
+
+        Ok(table)
+    }
+
+    /// Add the entries of a hosts, dnsmasq, ISC dhcpd or systemd-networkd file.
+    pub(crate) fn parse(&mut self, contents: &str) {
+        if contents
+            .lines()
+            .any(|line| line.trim_start().starts_with("lease "))
+        {
+            self.parse_dhcpd_leases(contents);
+        } else if contents.lines().any(|line| line.starts_with("ADDRESS=")) {
+            self.parse_networkd_lease(contents);
+        } else {
+            self.parse_lines(contents);
+        }
+    }
+
+    /// Hosts file lines (`IP name aliases...`) and dnsmasq leases
+    /// (`expiry MAC IP name client-id`, with `*` for an unknown name).
+    fn parse_lines(&mut self, contents: &str) {
+        for line in contents.lines() {
+            let line = line.split_once('#').map_or(line, |(line, _)| line);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if let [address, names @ ..] = fields.as_slice()
+                && let Some(ip_addr) = parse_address(address)
+            {
+                for name in names {
+                    self.insert(ip_addr, name);
+                }
+            } else if let [expiry, _mac, address, name, ..] = fields.as_slice()
+                && expiry.parse::<u64>().is_ok()
+                && *name != "*"
+                && let Some(ip_addr) = parse_address(address)
+            {
+                self.insert(ip_addr, name);
+            }
+        }
+    }
+
+    /// ISC dhcpd leases. Later blocks for an address replace earlier ones.
+    fn parse_dhcpd_leases(&mut self, contents: &str) {
+        let mut lease = None;
+        for line in contents.lines() {
+            let line = line.trim().trim_end_matches(';');
+            if let Some(rest) = line.strip_prefix("lease ") {
+                lease = rest.trim_end_matches('{').trim().parse::<IpAddr>().ok();
+            } else if line == "}" {
+                lease = None;
+            } else if let Some(ip_addr) = lease
+                && let Some(name) = line.strip_prefix("client-hostname ")
+            {
+                let name = name.trim().trim_matches('"');
+                self.names.insert(ip_addr, vec![name.to_string()]);
+            }
+        }
+    }
+
+    /// systemd-networkd `KEY=value` lease files.
+    fn parse_networkd_lease(&mut self, contents: &str) {
+        let field = |key: &str| {
+            contents
+                .lines()
+                .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+        };
+        if let (Some(address), Some(name)) = (field("ADDRESS"), field("HOSTNAME"))
+            && let Some(ip_addr) = parse_address(address)
+        {
+            self.insert(ip_addr, name);
+        }
+    }
+
+    fn insert(&mut self, ip_addr: IpAddr, name: &str) {
+        let names = self.names.entry(ip_addr).or_default();
+        if !names.iter().any(|known| known == name) {
+            names.push(name.to_string());
+        }
+    }
+
+    /// Names listed for `ip_addr`, canonical name first.
+    pub(crate) fn lookup(&self, ip_addr: &IpAddr) -> &[String] {
+        self.names.get(ip_addr).map_or(&[], Vec::as_slice)
+    }
+
+    /// Addresses `name` is listed for, ignoring case.
+    pub(crate) fn addresses(&self, name: &str) -> Vec<IpAddr> {
+        self.names
+            .iter()
+            .filter(|(_, names)| names.iter().any(|known| known.eq_ignore_ascii_case(name)))
+            .map(|(ip_addr, _)| *ip_addr)
+            .collect()
+    }
+}
+
+/// An address, ignoring any IPv6 zone.
+fn parse_address(address: &str) -> Option<IpAddr> {
+    address.split('%').next()?.parse().ok()
+}
+
+fn read(path: &Path) -> Result<String, Error> {
+    std::fs::read_to_string(path).map_err(|source| Error::Io {
+        context: format!("failed to read hosts file '{}'", path.display()),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::HostsTable;
+
+    fn lookup(contents: &str, ip_addr: &str) -> Vec<String> {
+        let mut table = HostsTable::default();
+        table.parse(contents);
+        table.lookup(&ip_addr.parse::<IpAddr>().unwrap()).to_vec()
+    }
+
+    #[test]
+    fn hosts_files_list_canonical_names_then_aliases() {
+        let hosts = "127.0.0.1 localhost\n# lab\n192.168.1.10  nas.lab nas  # storage\nfe80::1%eth0 router\n";
+
+        assert_eq!(lookup(hosts, "192.168.1.10"), ["nas.lab", "nas"]);
+        assert_eq!(lookup(hosts, "fe80::1"), ["router"]);
+        assert!(lookup(hosts, "192.168.1.11").is_empty());
+    }
+
+    #[test]
+    fn dnsmasq_leases_skip_unknown_names() {
+        let leases = "1792323045 b8:27:eb:12:34:56 192.168.1.20 raspberrypi 01:b8:27:eb:12:34:56\n\
+                      1792323046 3c:52:82:aa:bb:cc 192.168.1.21 * *\n";
+
+        assert_eq!(lookup(leases, "192.168.1.20"), ["raspberrypi"]);
+        assert!(lookup(leases, "192.168.1.21").is_empty());
+    }
+
+    #[test]
+    fn dhcpd_leases_keep_the_latest_client_hostname() {
+        let leases = r#"
+lease 192.168.1.30 {
+  starts 4 2026/10/15 09:00:00;
+  hardware ethernet 3c:52:82:aa:bb:cc;
+  client-hostname "old-laptop";
+}
+lease 192.168.1.30 {
+  starts 6 2026/10/17 09:00:00;
+  client-hostname "DESKTOP-7F3K2";
+}
+"#;
+
+        assert_eq!(lookup(leases, "192.168.1.30"), ["DESKTOP-7F3K2"]);
+    }
+
+    #[test]
+    fn names_lead_back_to_every_address_listing_them() {
+        let mut table = HostsTable::default();
+        table.parse("192.168.1.10 nas.lab nas\nfd00::10 nas.lab\n");
+
+        let mut addresses = table.addresses("NAS.lab");
+        addresses.sort();
+        assert_eq!(
+            addresses,
+            [
+                "192.168.1.10".parse::<IpAddr>().unwrap(),
+                "fd00::10".parse().unwrap()
+            ]
+        );
+        assert!(table.addresses("printer").is_empty());
+    }
+
+    #[test]
+    fn networkd_leases_name_their_address() {
+        let lease = "# This is private data. Do not parse.\nADDRESS=192.168.1.40\nNETMASK=255.255.255.0\nHOSTNAME=printer\n";
+
+        assert_eq!(lookup(lease, "192.168.1.40"), ["printer"]);
+    }
+}
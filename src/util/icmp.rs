@@ -0,0 +1,490 @@
+//! Echo requests for the unicast sweep, sent from one raw ICMP socket per
+//! source address instead of one socket per target.
+//!
+//! Each socket has an identifier of its own and numbers its requests in
+//! sequence. A single task per socket reads every reply and hands it to the
+//! request with the same target and sequence number, so a sweep needs only a
+//! few file descriptors however many hosts it covers. Sending and waiting
+//! are separate steps, so a sweep can send to every host before the first
+//! reply is due.
+//!
+//! Sequence numbers wrap around after 65,536 requests on a socket. A number
+//! that still has a request to the same target waiting is skipped, but a
+//! reply that arrives after its request timed out can be taken for a later
+//! request that reuses its number, which on a busy socket needs a reply
+//! delayed by the time it takes to send that many requests.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use super::{Reply, is_unreachable};
+
+const ECHO_REQUEST_V4: u8 = 8;
+const ECHO_REPLY_V4: u8 = 0;
+const ECHO_REQUEST_V6: u8 = 128;
+const ECHO_REPLY_V6: u8 = 129;
+const PAYLOAD: &[u8] = b"pingall";
+
+/// A target and the sequence number of the request sent to it.
+type RequestKey = (IpAddr, u16);
+
+/// An address family (`true` for IPv6) and source address.
+type SocketKey = (bool, Option<IpAddr>);
+
+type Sockets = Mutex<HashMap<SocketKey, Arc<EchoSocket>>>;
+
+/// The ICMP sockets of a sweep, opened as they are first needed.
+#[derive(Debug)]
+pub(crate) struct IcmpEngine {
+    /// [`Type::RAW`], or [`Type::DGRAM`] for unprivileged sockets.
+    socket_type: Type,
+    sockets: Arc<Sockets>,
+}
+
+#[derive(Debug)]
+struct EchoSocket {
+    socket: Arc<UdpSocket>,
+    identifier: u16,
+    pending: Arc<Mutex<Pending>>,
+    receiver: JoinHandle<()>,
+}
+
+/// The requests of one socket that are waiting for a reply.
+#[derive(Debug, Default)]
+struct Pending {
+    waiters: HashMap<RequestKey, oneshot::Sender<io::Result<Instant>>>,
+    /// Why the receive loop stopped, after which no reply can arrive.
+    failed: Option<io::Error>,
+    next_sequence: u16,
+}
+
+impl Pending {
+    /// Wait for a reply from `ip_addr` to a new request, numbered with the
+    /// next sequence number that no waiting request to `ip_addr` has.
+    fn register(
+        &mut self,
+        ip_addr: IpAddr,
+    ) -> io::Result<(u16, oneshot::Receiver<io::Result<Instant>>)> {
+        if let Some(error) = &self.failed {
+            return Err(copy_error(error));
+        }
+        let sequence = (0..=u16::MAX)
+            .map(|offset| self.next_sequence.wrapping_add(offset))
+            .find(|sequence| !self.waiters.contains_key(&(ip_addr, *sequence)))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!("every ICMP sequence number to {} is in use", ip_addr),
+                )
+            })?;
+        self.next_sequence = sequence.wrapping_add(1);
+        let (sender, receiver) = oneshot::channel();
+        self.waiters.insert((ip_addr, sequence), sender);
+        Ok((sequence, receiver))
+    }
+
+    /// Fail every waiting request, and every later one, with `error`.
+    fn fail(&mut self, error: io::Error) {
+        for (_, waiter) in self.waiters.drain() {
+            let _ = waiter.send(Err(copy_error(&error)));
+        }
+        self.failed = Some(error);
+    }
+}
+
+fn copy_error(error: &io::Error) -> io::Error {
+    io::Error::new(error.kind(), error.to_string())
+}
+
+impl Drop for EchoSocket {
+    fn drop(&mut self) {
+        self.receiver.abort();
+    }
+}
+
+impl IcmpEngine {
+    pub(crate) fn new(socket_type: Type) -> Self {
+        Self {
+            socket_type,
+            sockets: Arc::default(),
+        }
+    }
+
+    /// Send an echo request to `ip_addr` from `source`, or from the address
+    /// the system picks. Returns `None` if the target is unreachable.
+    ///
+    /// Fails if the socket could not be opened or the request could not be
+    /// sent, for example because the send buffer is full.
+    pub(crate) async fn send(
+        &self,
+        ip_addr: IpAddr,
+        source: Option<IpAddr>,
+    ) -> io::Result<Option<Echo>> {
+        let socket = self.socket(ip_addr.is_ipv6(), source)?;
+        let (sequence, receiver) = socket.pending.lock().unwrap().register(ip_addr)?;
+        // Dropping the echo withdraws the request, whether or not it was sent.
+        let echo = Echo {
+            socket,
+            key: (ip_addr, sequence),
+            sent_at: Instant::now(),
+            receiver,
+        };
+
+        let packet = echo_request(ip_addr.is_ipv6(), echo.socket.identifier, sequence);
+        match echo
+            .socket
+            .socket
+            .send_to(&packet, SocketAddr::new(ip_addr, 0))
+            .await
+        {
+            Ok(_) => Ok(Some(echo)),
+            Err(e) if is_unreachable(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The socket for `source`, opening it on first use.
+    fn socket(&self, ipv6: bool, source: Option<IpAddr>) -> io::Result<Arc<EchoSocket>> {
+        let mut sockets = self.sockets.lock().unwrap();
+        if let Some(socket) = sockets.get(&(ipv6, source)) {
+            return Ok(socket.clone());
+        }
+
+        let socket = open_socket(ipv6, source, self.socket_type)?;
+        let socket = Arc::new(UdpSocket::from_std(socket.into())?);
+        // Tell apart the sockets of this process and of other pingers. The
+        // kernel replaces the identifier of datagram sockets with their port,
+        // and only hands them their own replies.
+        let identifier = (std::process::id() as u16).wrapping_add(sockets.len() as u16);
+        let expected = (self.socket_type == Type::RAW).then_some(identifier);
+        let pending = Arc::<Mutex<Pending>>::default();
+        let receiver = tokio::spawn(receive(
+            socket.clone(),
+            ipv6,
+            expected,
+            pending.clone(),
+            Arc::downgrade(&self.sockets),
+            (ipv6, source),
+        ));
+        let socket = Arc::new(EchoSocket {
+            socket,
+            identifier,
+            pending,
+            receiver,
+        });
+        sockets.insert((ipv6, source), socket.clone());
+
+        Ok(socket)
+    }
+}
+
+/// An echo request that was sent and may still be answered.
+#[derive(Debug)]
+pub(crate) struct Echo {
+    socket: Arc<EchoSocket>,
+    key: RequestKey,
+    sent_at: Instant,
+    receiver: oneshot::Receiver<io::Result<Instant>>,
+}
+
+impl Echo {
+    /// Wait up to `timeout` from now for the reply.
+    ///
+    /// Fails if the socket stopped receiving before the reply arrived.
+    pub(crate) async fn reply(mut self, timeout: Duration) -> io::Result<Option<Reply>> {
+        let Ok(received) = tokio::time::timeout(timeout, &mut self.receiver).await else {
+            return Ok(None);
+        };
+        let received_at = received.map_err(|_| io::Error::other("ICMP receive loop stopped"))??;
+        Ok(Some(Reply {
+            rtt: Some(received_at.duration_since(self.sent_at)),
+            open_ports: Vec::new(),
+        }))
+    }
+}
+
+impl Drop for Echo {
+    fn drop(&mut self) {
+        self.socket
+            .pending
+            .lock()
+            .unwrap()
+            .waiters
+            .remove(&self.key);
+    }
+}
+
+/// Open a non-blocking ICMP socket. Like UDP sockets, ICMP sockets take and
+/// return whole datagrams, so they can be driven as a [`UdpSocket`].
+pub(super) fn open_socket(
+    ipv6: bool,
+    source: Option<IpAddr>,
+    socket_type: Type,
+) -> io::Result<Socket> {
+    let socket = if ipv6 {
+        Socket::new(Domain::IPV6, socket_type, Some(Protocol::ICMPV6))?
+    } else {
+        Socket::new(Domain::IPV4, socket_type, Some(Protocol::ICMPV4))?
+    };
+    socket.set_nonblocking(true)?;
+    if let Some(source) = source {
+        socket.bind(&SocketAddr::new(source, 0).into())?;
+    }
+    Ok(socket)
+}
+
+/// Receive errors after which the socket still works: ICMP errors some
+/// systems report on a later read, and interrupted reads.
+fn is_transient(error: &io::Error) -> bool {
+    is_unreachable(error)
+        || matches!(
+            error.kind(),
+            io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::Interrupted
+                | io::ErrorKind::WouldBlock
+        )
+}
+
+/// Hand each echo reply that arrives on `socket` to the request waiting for
+/// it, skipping replies to other pingers when `identifier` is given.
+///
+/// On an error the socket cannot recover from, the socket's requests fail
+/// and it is dropped from `sockets`, so later requests open a new one.
+async fn receive(
+    socket: Arc<UdpSocket>,
+    ipv6: bool,
+    identifier: Option<u16>,
+    pending: Arc<Mutex<Pending>>,
+    sockets: Weak<Sockets>,
+    key: SocketKey,
+) {
+    let mut buffer = [0u8; 1500];
+    loop {
+        let (received, peer) = match socket.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(e) if is_transient(&e) => continue,
+            Err(e) => {
+                retire(&sockets, key, &pending, e);
+                return;
+            }
+        };
+        let received_at = Instant::now();
+        let Some((reply_identifier, sequence)) = parse_echo_reply(&buffer[..received], ipv6) else {
+            continue;
+        };
+        if identifier.is_some_and(|identifier| identifier != reply_identifier) {
+            continue;
+        }
+        let waiter = pending
+            .lock()
+            .unwrap()
+            .waiters
+            .remove(&(peer.ip(), sequence));
+        if let Some(waiter) = waiter {
+            let _ = waiter.send(Ok(received_at));
+        }
+    }
+}
+
+/// Fail the requests of the socket at `key` with `error`, and drop the socket
+/// unless it was already replaced.
+fn retire(
+    sockets: &Weak<Sockets>,
+    key: SocketKey,
+    pending: &Arc<Mutex<Pending>>,
+    error: io::Error,
+) {
+    let error = io::Error::new(
+        error.kind(),
+        format!("receiving ICMP replies failed: {}", error),
+    );
+    pending.lock().unwrap().fail(error);
+
+    let Some(sockets) = sockets.upgrade() else {
+        return;
+    };
+    let mut sockets = sockets.lock().unwrap();
+    if sockets
+        .get(&key)
+        .is_some_and(|socket| Arc::ptr_eq(&socket.pending, pending))
+    {
+        let retired = sockets.remove(&key);
+        drop(sockets);
+        drop(retired);
+    }
+}
+
+/// An ICMP or ICMPv6 echo request. The kernel fills in the ICMPv6 checksum,
+/// which covers the IPv6 pseudo-header.
+fn echo_request(ipv6: bool, identifier: u16, sequence: u16) -> Vec<u8> {
+    let request_type = if ipv6 {
+        ECHO_REQUEST_V6
+    } else {
+        ECHO_REQUEST_V4
+    };
+    let mut packet = vec![request_type, 0, 0, 0];
+    packet.extend_from_slice(&identifier.to_be_bytes());
+    packet.extend_from_slice(&sequence.to_be_bytes());
+    packet.extend_from_slice(PAYLOAD);
+    if !ipv6 {
+        let checksum = checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+    packet
+}
+
+/// The Internet checksum (RFC 1071) of `data`.
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum = data
+        .chunks(2)
+        .map(|chunk| u32::from(u16::from_be_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)])))
+        .sum::<u32>();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// The identifier and sequence number of an echo reply. Raw IPv4 sockets,
+/// and datagram sockets on some systems, return the IP header too, which is
+/// skipped.
+fn parse_echo_reply(packet: &[u8], ipv6: bool) -> Option<(u16, u16)> {
+    let message = match packet.first()? >> 4 {
+        4 if !ipv6 => packet.get(usize::from(packet[0] & 0x0f) * 4..)?,
+        _ => packet,
+    };
+    let reply_type = if ipv6 { ECHO_REPLY_V6 } else { ECHO_REPLY_V4 };
+    if *message.first()? != reply_type || message.len() < 8 {
+        return None;
+    }
+
+    let identifier = u16::from_be_bytes([message[4], message[5]]);
+    let sequence = u16::from_be_bytes([message[6], message[7]]);
+    Some((identifier, sequence))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use socket2::Type;
+
+    use super::{IcmpEngine, Pending, checksum, echo_request, parse_echo_reply, retire};
+
+    #[test]
+    fn wrapped_sequence_numbers_skip_requests_still_waiting() {
+        let host = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 7));
+        let other = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 8));
+        let mut pending = Pending {
+            next_sequence: u16::MAX,
+            ..Pending::default()
+        };
+
+        let (last, _last) = pending.register(host).unwrap();
+        let (first, _first) = pending.register(host).unwrap();
+        assert_eq!((last, first), (u16::MAX, 0));
+
+        // Come back around while both requests are still waiting.
+        pending.next_sequence = u16::MAX;
+        assert_eq!(pending.register(host).unwrap().0, 1);
+        assert_eq!(pending.next_sequence, 2);
+        pending.next_sequence = u16::MAX;
+        assert_eq!(pending.register(other).unwrap().0, u16::MAX);
+    }
+
+    #[test]
+    fn echo_requests_carry_a_valid_checksum() {
+        let request = echo_request(false, 0x1234, 7);
+
+        assert_eq!(&request[..2], &[8, 0]);
+        assert_eq!(&request[4..8], &[0x12, 0x34, 0, 7]);
+        assert_eq!(checksum(&request), 0);
+    }
+
+    #[test]
+    fn replies_are_read_with_or_without_an_ip_header() {
+        let mut reply = echo_request(false, 0x1234, 7);
+        reply[0] = 0;
+        let mut with_header = vec![0x45; 20];
+        with_header.extend_from_slice(&reply);
+
+        assert_eq!(parse_echo_reply(&reply, false), Some((0x1234, 7)));
+        assert_eq!(parse_echo_reply(&with_header, false), Some((0x1234, 7)));
+        // Requests, and replies of the other family, are not replies here.
+        assert_eq!(parse_echo_reply(&echo_request(false, 1, 1), false), None);
+        assert_eq!(parse_echo_reply(&reply, true), None);
+    }
+
+    #[test]
+    fn loopback_hosts_answer_through_one_socket() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let engine = Arc::new(IcmpEngine::new(Type::RAW));
+                let mut pings = tokio::task::JoinSet::new();
+                for host in 1..=20 {
+                    let engine = engine.clone();
+                    let ip_addr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, host));
+                    pings.spawn(async move {
+                        let echo = engine.send(ip_addr, None).await?;
+                        echo.unwrap().reply(Duration::from_secs(2)).await
+                    });
+                }
+
+                while let Some(reply) = pings.join_next().await {
+                    match reply.unwrap() {
+                        // Raw sockets need CAP_NET_RAW.
+                        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => return,
+                        reply => assert!(reply.unwrap().is_some()),
+                    }
+                }
+                assert_eq!(engine.sockets.lock().unwrap().len(), 1);
+            });
+    }
+
+    #[test]
+    fn failed_sockets_fail_their_requests_and_are_reopened() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let engine = IcmpEngine::new(Type::RAW);
+                let socket = match engine.socket(false, None) {
+                    Err(e) if e.kind() == io::ErrorKind::PermissionDenied => return,
+                    socket => socket.unwrap(),
+                };
+                let target = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 200));
+                let (_, waiting) = socket.pending.lock().unwrap().register(target).unwrap();
+
+                retire(
+                    &Arc::downgrade(&engine.sockets),
+                    (false, None),
+                    &socket.pending,
+                    io::Error::from(io::ErrorKind::NotConnected),
+                );
+
+                let error = waiting.await.unwrap().unwrap_err();
+                assert_eq!(error.kind(), io::ErrorKind::NotConnected);
+                assert!(socket.pending.lock().unwrap().register(target).is_err());
+                assert!(engine.sockets.lock().unwrap().is_empty());
+                let reopened = engine.socket(false, None).unwrap();
+                assert!(!Arc::ptr_eq(&reopened, &socket));
+            });
+    }
+}
@@ -0,0 +1,363 @@
+//! Multicast DNS reverse lookups, and the address lookups that confirm them.
+//!
+//! Queries are sent as legacy unicast queries (RFC 6762, section 6.7) from
+//! one ephemeral port per interface, so responders answer straight back to
+//! that socket and no mDNS daemon is involved.
+
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use super::InterfaceAddress;
+use super::dns::{RecordData, RecordType, ptr_name, query, records};
+
+pub(super) const MDNS_PORT: u16 = 5353;
+pub(super) const MDNS_IPV4: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub(super) const MDNS_IPV6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb);
+
+/// Lookups waiting for an answer, keyed by the name they asked about.
+type Pending = Arc<Mutex<HashMap<String, Vec<oneshot::Sender<Vec<RecordData>>>>>>;
+
+/// Sends reverse queries for many hosts over a few shared sockets.
+#[derive(Debug)]
+pub(crate) struct MdnsResolver {
+    sockets: Vec<MdnsSocket>,
+    pending: Pending,
+    receivers: Vec<JoinHandle<()>>,
+    timeout: Duration,
+}
+
+#[derive(Debug)]
+struct MdnsSocket {
+    socket: Arc<UdpSocket>,
+    destination: SocketAddr,
+    interface: String,
+}
+
+impl MdnsResolver {
+    /// Open one socket per interface and address family, waiting up to
+    /// `timeout` for each answer.
+    ///
+    /// Returns `None` when no socket could be opened.
+    pub(crate) fn open(addresses: &[InterfaceAddress], timeout: Duration) -> Option<Self> {
+        let mut opened = BTreeSet::new();
+        let mut sockets = Vec::new();
+        for address in addresses {
+            let (interface, socket, destination) = match address {
+                InterfaceAddress::V4 { ip, interface, .. } => (
+                    interface,
+                    ipv4_socket(*ip),
+                    SocketAddr::from((MDNS_IPV4, MDNS_PORT)),
+                ),
+                InterfaceAddress::V6 {
+                    interface,
+                    index: Some(index),
+                    ..
+                } => (
+                    interface,
+                    ipv6_socket(*index),
+                    SocketAddr::V6(SocketAddrV6::new(MDNS_IPV6, MDNS_PORT, 0, *index)),
+                ),
+                InterfaceAddress::V6 { index: None, .. } => continue,
+            };
+            if !opened.insert((interface.clone(), destination.is_ipv6())) {
+                continue;
+            }
+            if let Ok(socket) = socket {
+                sockets.push(MdnsSocket {
+                    socket: Arc::new(socket),
+                    destination,
+                    interface: interface.clone(),
+                });
+            }
+        }
+
+        (!sockets.is_empty()).then(|| Self::from_sockets(sockets, timeout))
+    }
+
+    fn from_sockets(sockets: Vec<MdnsSocket>, timeout: Duration) -> Self {
+        let pending = Pending::default();
+        let receivers = sockets
+            .iter()
+            .map(|socket| tokio::spawn(receive(socket.socket.clone(), pending.clone())))
+            .collect();
+
+        Self {
+            sockets,
+            pending,
+            receivers,
+            timeout,
+        }
+    }
+
+    /// Ask for the names of `ip_addr` on the interface it was found on, or on
+    /// every interface of its address family when that is unknown.
+    pub(crate) async fn resolve(&self, ip_addr: &IpAddr, scope: Option<&str>) -> Vec<String> {
+        let records = self
+            .ask(&ptr_name(*ip_addr), RecordType::Ptr, ip_addr, scope)
+            .await;
+        records
+            .into_iter()
+            .filter_map(|record| match record {
+                RecordData::Ptr(hostname) => Some(hostname),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Ask for the addresses of `name` in the family of `ip_addr`, on the same
+    /// interfaces [`resolve`](Self::resolve) would use.
+    pub(crate) async fn lookup(
+        &self,
+        name: &str,
+        ip_addr: &IpAddr,
+        scope: Option<&str>,
+    ) -> Vec<IpAddr> {
+        let record_type = RecordType::Address {
+            ipv6: ip_addr.is_ipv6(),
+        };
+        let records = self.ask(name, record_type, ip_addr, scope).await;
+        records
+            .into_iter()
+            .filter_map(|record| match record {
+                RecordData::Address(ip_addr) => Some(ip_addr),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Send one query and wait for the first response with records about `name`.
+    async fn ask(
+        &self,
+        name: &str,
+        record_type: RecordType,
+        ip_addr: &IpAddr,
+        scope: Option<&str>,
+    ) -> Vec<RecordData> {
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+        let (sender, receiver) = oneshot::channel();
+        self.pending
+            .lock()
+            .unwrap()
+            .entry(name.clone())
+            .or_default()
+            .push(sender);
+
+        let packet = query(0, 0, &name, record_type);
+        for socket in self.sockets_for(ip_addr, scope) {
+            let _ = socket.socket.send_to(&packet, socket.destination).await;
+        }
+        let answer = tokio::time::timeout(self.timeout, receiver).await;
+
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(waiters) = pending.get_mut(&name) {
+            waiters.retain(|waiter| !waiter.is_closed());
+            if waiters.is_empty() {
+                pending.remove(&name);
+            }
+        }
+
+        answer.ok().and_then(Result::ok).unwrap_or_default()
+    }
+
+    fn sockets_for(&self, ip_addr: &IpAddr, scope: Option<&str>) -> Vec<&MdnsSocket> {
+        let mut sockets: Vec<_> = self
+            .sockets
+            .iter()
+            .filter(|socket| socket.destination.is_ipv4() == ip_addr.is_ipv4())
+            .collect();
+        if let Some(scope) = scope
+            && sockets.iter().any(|socket| socket.interface == scope)
+        {
+            sockets.retain(|socket| socket.interface == scope);
+        }
+
+        sockets
+    }
+}
+
+impl Drop for MdnsResolver {
+    fn drop(&mut self) {
+        for receiver in &self.receivers {
+            receiver.abort();
+        }
+    }
+}
+
+/// Hand the records that arrive on `socket` to the lookups waiting for them,
+/// all records about one name together.
+async fn receive(socket: Arc<UdpSocket>, pending: Pending) {
+    let mut buffer = [0u8; 9000];
+    loop {
+        let received = match socket.recv(&mut buffer).await {
+            Ok(received) => received,
+            // Windows reports ICMP port unreachable errors on later reads.
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+            Err(_) => return,
+        };
+
+        let mut answers: HashMap<String, Vec<RecordData>> = HashMap::new();
+        for (name, record) in records(&buffer[..received]) {
+            answers
+                .entry(name.to_ascii_lowercase())
+                .or_default()
+                .push(record);
+        }
+        for (name, records) in answers {
+            let waiters = pending.lock().unwrap().remove(&name);
+            for waiter in waiters.into_iter().flatten() {
+                let _ = waiter.send(records.clone());
+            }
+        }
+    }
+}
+
+pub(super) fn ipv4_socket(interface_ip: Ipv4Addr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_multicast_if_v4(&interface_ip)?;
+    socket.set_multicast_ttl_v4(255)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)).into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+pub(super) fn ipv6_socket(index: u32) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_only_v6(true)?;
+    socket.set_multicast_if_v6(index)?;
+    socket.set_multicast_hops_v6(255)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)).into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::net::UdpSocket;
+
+    use super::{MdnsResolver, MdnsSocket};
+
+    /// Answer a PTR query with `hostname`, the way a responder answers a
+    /// legacy unicast query.
+    fn ptr_answer(query: &[u8], hostname: &str) -> Vec<u8> {
+        let mut response = query.to_vec();
+        response[2] |= 0x84;
+        response[7] = 1;
+        response.extend_from_slice(&[0xc0, 12, 0, 12, 0x80, 1, 0, 0, 0, 10]);
+        let mut data = Vec::new();
+        for label in hostname.split('.') {
+            data.push(label.len() as u8);
+            data.extend_from_slice(label.as_bytes());
+        }
+        data.push(0);
+        response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        response.extend_from_slice(&data);
+        response
+    }
+
+    #[test]
+    fn concurrent_lookups_share_one_socket() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let responder = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+                let destination = responder.local_addr().unwrap();
+                let responder = tokio::spawn(async move {
+                    let mut buffer = [0u8; 512];
+                    let mut peers = Vec::new();
+                    for _ in 0..2 {
+                        let (received, peer) = responder.recv_from(&mut buffer).await.unwrap();
+                        let hostname = if buffer[12..received].starts_with(b"\x0210") {
+                            "nas.local"
+                        } else {
+                            "printer.local"
+                        };
+                        let response = ptr_answer(&buffer[..received], hostname);
+                        responder.send_to(&response, peer).await.unwrap();
+                        peers.push(peer);
+                    }
+                    peers
+                });
+
+                let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+                let resolver = Arc::new(MdnsResolver::from_sockets(
+                    vec![MdnsSocket {
+                        socket: Arc::new(socket),
+                        destination,
+                        interface: "eth0".to_string(),
+                    }],
+                    Duration::from_secs(2),
+                ));
+
+                let lookup = |ip_addr: [u8; 4], scope: Option<&'static str>| {
+                    let resolver = resolver.clone();
+                    tokio::spawn(async move {
+                        resolver
+                            .resolve(&IpAddr::V4(Ipv4Addr::from(ip_addr)), scope)
+                            .await
+                    })
+                };
+                let nas = lookup([192, 168, 1, 10], Some("eth0"));
+                let printer = lookup([192, 168, 1, 20], None);
+
+                assert_eq!(nas.await.unwrap(), vec!["nas.local".to_string()]);
+                assert_eq!(printer.await.unwrap(), vec!["printer.local".to_string()]);
+                let peers = responder.await.unwrap();
+                assert_eq!(peers[0], peers[1]);
+                assert!(resolver.pending.lock().unwrap().is_empty());
+            });
+    }
+
+    #[test]
+    fn address_lookups_collect_every_address() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let responder = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+                let destination = responder.local_addr().unwrap();
+                tokio::spawn(async move {
+                    let mut buffer = [0u8; 512];
+                    let (received, peer) = responder.recv_from(&mut buffer).await.unwrap();
+                    let mut response = buffer[..received].to_vec();
+                    response[2] |= 0x84;
+                    response[7] = 2;
+                    for last_octet in [10, 11] {
+                        response.extend_from_slice(&[0xc0, 12, 0, 1, 0x80, 1, 0, 0, 0, 120, 0, 4]);
+                        response.extend_from_slice(&[192, 168, 1, last_octet]);
+                    }
+                    responder.send_to(&response, peer).await.unwrap();
+                });
+
+                let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+                let resolver = MdnsResolver::from_sockets(
+                    vec![MdnsSocket {
+                        socket: Arc::new(socket),
+                        destination,
+                        interface: "eth0".to_string(),
+                    }],
+                    Duration::from_secs(2),
+                );
+                let ip_addr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10));
+
+                assert_eq!(
+                    resolver.lookup("NAS.local.", &ip_addr, None).await,
+                    vec![ip_addr, IpAddr::V4(Ipv4Addr::new(192, 168, 1, 11))]
+                );
+            });
+    }
+}
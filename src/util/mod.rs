@@ -1,19 +1,95 @@
 use std::collections::BTreeSet;
+use std::io;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
 use std::process::Stdio;
-#[cfg(unix)]
 use std::time::Duration;
 
 use if_addrs::{IfAddr, get_if_addrs};
 use tokio::process::Command;
 
+use crate::resolve::system_confirm;
+use crate::{ConfirmFuture, Error, ResolveFuture, Resolver, ResolverKind, ScanOptions};
+
 #[cfg(unix)]
 use tiny_ping::{Pinger, SocketType};
 
+mod arp;
+mod capabilities;
+mod discovery;
+mod dns;
+mod dnssd;
+mod hosts;
+mod icmp;
+mod mdns;
+mod neighbours;
+mod netbios;
+mod oui;
+mod tcp;
+mod throttle;
+
+pub(crate) use arp::ArpSocket;
+pub use capabilities::{BackendSelection, Capabilities};
+pub(crate) use discovery::discover;
+pub(crate) use dnssd::browse;
+use hosts::HostsTable;
+pub(crate) use icmp::IcmpEngine;
+use mdns::MdnsResolver;
+pub(crate) use neighbours::NeighbourTable;
+pub(crate) use oui::{DEFAULT_OUI_PATHS, OuiDatabase};
+pub use tcp::TOP_TCP_PORTS;
+pub(crate) use tcp::{open_ports, tcp_ping, tcp_ping_ports};
+pub(crate) use throttle::{Throttle, ThrottlePermit};
+
+/// Mechanism used to send probes and receive replies.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum PingBackend {
+    /// The system `ping` command.
     System,
+    /// Raw ICMP sockets opened by `pingall` itself.
     RawSocket,
+    /// Unprivileged ICMP datagram sockets. On Linux, the process needs a
+    /// group within `net.ipv4.ping_group_range`.
+    Datagram,
+    /// ARP who-has requests on a raw packet socket. IPv4 on Linux only.
+    Arp,
+    /// TCP connections to [`ScanOptions::tcp_ports`]. A host is alive when
+    /// any port accepts or refuses the connection.
+    Tcp,
+    /// UDP service discovery queries, for results of
+    /// [`ScanOptions::udp_discovery`]. As a [`ScanOptions::method`], it picks
+    /// a backend automatically.
+    Udp,
+}
+
+impl std::fmt::Display for PingBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PingBackend::System => "system",
+            PingBackend::RawSocket => "raw-socket",
+            PingBackend::Datagram => "datagram",
+            PingBackend::Arp => "arp",
+            PingBackend::Tcp => "tcp",
+            PingBackend::Udp => "udp",
+        })
+    }
+}
+
+impl std::str::FromStr for PingBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "system" => Ok(PingBackend::System),
+            "raw" | "raw-socket" => Ok(PingBackend::RawSocket),
+            "datagram" | "dgram" => Ok(PingBackend::Datagram),
+            "arp" => Ok(PingBackend::Arp),
+            "tcp" => Ok(PingBackend::Tcp),
+            _ => Err(format!(
+                "unknown method '{}', expected one of: system, raw, datagram, arp, tcp",
+                s
+            )),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -37,39 +113,101 @@ pub fn raw_socket_supported() -> bool {
     current_runtime_platform() == RuntimePlatform::Unix
 }
 
+/// Path of the Linux setting that lists the groups allowed to open ICMP
+/// datagram sockets.
+#[cfg(target_os = "linux")]
+const PING_GROUP_RANGE: &str = "/proc/sys/net/ipv4/ping_group_range";
+
+/// Parse `net.ipv4.ping_group_range`: the lowest and highest allowed group
+/// IDs. The default, `1 0`, allows none.
+#[cfg(target_os = "linux")]
+fn parse_ping_group_range(contents: &str) -> Option<(u32, u32)> {
+    let mut bounds = contents.split_whitespace().map(str::parse);
+    let low = bounds.next()?.ok()?;
+    let high = bounds.next()?.ok()?;
+    Some((low, high))
+}
+
+/// Whether this process may open ICMP datagram sockets without privileges:
+/// whether its effective group or a supplementary group is within
+/// `net.ipv4.ping_group_range`.
+#[cfg(target_os = "linux")]
+pub(crate) fn datagram_socket_permitted() -> bool {
+    let Some((low, high)) = std::fs::read_to_string(PING_GROUP_RANGE)
+        .ok()
+        .and_then(|contents| parse_ping_group_range(&contents))
+    else {
+        return false;
+    };
+
+    // SAFETY: getegid(2) takes no arguments and cannot fail.
+    let mut groups = vec![unsafe { libc::getegid() }];
+    // SAFETY: a size of zero only asks for the number of groups; the null
+    // buffer is not written to.
+    let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    if count > 0 {
+        let mut supplementary = vec![0; count as usize];
+        // SAFETY: `supplementary` holds exactly `count` entries, so at most
+        // that many are written. A group added since the first call makes
+        // this fail with -1 instead of overflowing.
+        let count = unsafe { libc::getgroups(count, supplementary.as_mut_ptr()) };
+        supplementary.truncate(count.max(0) as usize);
+        groups.extend(supplementary);
+    }
+    groups
+        .into_iter()
+        .any(|group| (low..=high).contains(&group))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn datagram_socket_permitted() -> bool {
+    false
+}
+
 fn select_ping_backend_for(
     platform: RuntimePlatform,
     raw_socket_requested: bool,
     system_ping_exists: bool,
-) -> Result<PingBackend, &'static str> {
+    datagram_permitted: bool,
+) -> Result<PingBackend, Error> {
     match platform {
         RuntimePlatform::Unix => {
-            if raw_socket_requested || !system_ping_exists {
+            if raw_socket_requested {
                 Ok(PingBackend::RawSocket)
-            } else {
+            } else if datagram_permitted {
+                Ok(PingBackend::Datagram)
+            } else if system_ping_exists {
                 Ok(PingBackend::System)
+            } else {
+                Ok(PingBackend::RawSocket)
             }
         }
         RuntimePlatform::NonUnix => {
             if system_ping_exists {
                 Ok(PingBackend::System)
             } else {
-                Err(
-                    "system `ping` command not found and raw sockets are unsupported on this platform",
-                )
+                Err(Error::PingNotFound)
             }
         }
     }
 }
 
+/// Pick the backend for ICMP probes. On Unix, raw sockets when requested,
+/// then unprivileged datagram sockets when permitted, then the system `ping`
+/// command, and raw sockets as a last resort.
+#[deprecated(
+    since = "2.2.0",
+    note = "use `BackendSelection`, which checks that the backend works"
+)]
 pub fn select_ping_backend(
     raw_socket_requested: bool,
     system_ping_exists: bool,
-) -> Result<PingBackend, &'static str> {
+) -> Result<PingBackend, Error> {
     select_ping_backend_for(
         current_runtime_platform(),
         raw_socket_requested,
         system_ping_exists,
+        datagram_socket_permitted(),
     )
 }
 
@@ -78,7 +216,20 @@ pub fn command_exists(command: &str) -> bool {
     which::which(command).is_ok()
 }
 
+/// Whether any hostname resolver is available: the system one, multicast DNS
+/// on a local interface, or DNS servers from `/etc/resolv.conf`.
+#[deprecated(
+    since = "2.2.0",
+    note = "the default resolvers always include local files and NetBIOS; a resolver asked for \
+            that cannot run fails the scan with `Error::ResolverUnavailable`"
+)]
 pub fn hostname_resolution_supported() -> bool {
+    system_resolver_supported()
+        || get_addresses(None).is_ok_and(|addresses| !addresses.is_empty())
+        || !dns::system_name_servers().is_empty()
+}
+
+fn system_resolver_supported() -> bool {
     if cfg!(target_os = "linux") {
         command_exists("avahi-resolve")
     } else {
@@ -88,7 +239,11 @@ pub fn hostname_resolution_supported() -> bool {
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) enum InterfaceAddress {
-    V4(Ipv4Addr),
+    V4 {
+        ip: Ipv4Addr,
+        prefix_len: u8,
+        interface: String,
+    },
     V6 {
         ip: Ipv6Addr,
         interface: String,
@@ -99,19 +254,38 @@ pub(crate) enum InterfaceAddress {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) struct DiscoveredAddress {
     pub(crate) ip_addr: IpAddr,
-    pub(crate) display_addr: String,
+    pub(crate) scope: Option<String>,
+    pub(crate) rtt: Option<Duration>,
+}
+
+/// A reply to a unicast echo request.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Reply {
+    pub(crate) rtt: Option<Duration>,
+    /// Ports that accepted a connection, for probes that connect to ports.
+    pub(crate) open_ports: Vec<u16>,
+}
+
+/// Whether a probe error only means the target can't be reached, as opposed
+/// to the probe not getting out of this host.
+fn is_unreachable(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::HostUnreachable
+            | io::ErrorKind::NetworkUnreachable
+            | io::ErrorKind::TimedOut
+    )
 }
 
 /// List the IP addresses associated with an interface.
 /// Given no interface, list all non-loopback IP addresses of all interfaces.
-pub(crate) fn get_addresses(interface: Option<String>) -> Vec<InterfaceAddress> {
-    let ifaddrs = match get_if_addrs() {
-        Ok(ifaddrs) => ifaddrs,
-        Err(_) => {
-            eprintln!("Failed to get network interfaces");
-            return Vec::new();
-        }
-    };
+pub(crate) fn get_addresses(interface: Option<String>) -> Result<Vec<InterfaceAddress>, Error> {
+    let ifaddrs = get_if_addrs().map_err(Error::InterfaceEnumeration)?;
+    if let Some(interface) = interface.as_ref()
+        && !ifaddrs.iter().any(|ifaddr| ifaddr.name == *interface)
+    {
+        return Err(Error::InterfaceNotFound(interface.clone()));
+    }
 
     let addresses = ifaddrs.into_iter().filter_map(|ifaddr| {
         if interface.as_ref().is_some_and(|name| ifaddr.name != *name) {
@@ -123,7 +297,11 @@ pub(crate) fn get_addresses(interface: Option<String>) -> Vec<InterfaceAddress>
         }
 
         match ifaddr.addr {
-            IfAddr::V4(addr) => Some(InterfaceAddress::V4(addr.ip)),
+            IfAddr::V4(addr) => Some(InterfaceAddress::V4 {
+                ip: addr.ip,
+                prefix_len: addr.prefixlen,
+                interface: ifaddr.name,
+            }),
             IfAddr::V6(addr) => {
                 if addr.ip.is_unspecified() || addr.ip.is_multicast() {
                     None
@@ -138,67 +316,194 @@ pub(crate) fn get_addresses(interface: Option<String>) -> Vec<InterfaceAddress>
         }
     });
 
-    addresses.collect()
+    Ok(addresses.collect())
 }
 
-#[allow(dead_code)]
-fn format_hostname(ip_addr: &IpAddr, hostname: &str) -> Option<String> {
+/// A hostname resolver chosen for a scan.
+#[derive(Debug)]
+pub(crate) enum HostnameResolver {
+    System,
+    Mdns(MdnsResolver),
+    Dns(Vec<SocketAddr>),
+    Netbios(Duration),
+    Hosts(HostsTable),
+}
+
+impl HostnameResolver {
+    /// Open the resolvers of [`ScanOptions::resolvers`], in order.
+    ///
+    /// When that is empty, Windows uses the system resolver. Elsewhere local
+    /// files come first, then multicast DNS and NetBIOS unless DNS servers
+    /// were given, and then DNS.
+    pub(crate) fn select(options: &ScanOptions) -> Result<Vec<Self>, Error> {
+        let dns_servers = options.dns_servers.as_slice();
+        let timeout = options.effective_timeout();
+        let hosts = || HostsTable::load(&options.hosts_files).map(HostnameResolver::Hosts);
+        let dns = || {
+            let servers = if dns_servers.is_empty() {
+                dns::system_name_servers()
+            } else {
+                dns_servers.to_vec()
+            };
+            (!servers.is_empty()).then_some(HostnameResolver::Dns(servers))
+        };
+        let mdns = || -> Result<_, Error> {
+            let addresses = get_addresses(options.interface.clone())?;
+            Ok(MdnsResolver::open(&addresses, timeout).map(HostnameResolver::Mdns))
+        };
+
+        if options.resolvers.is_empty() {
+            return Ok(match dns_servers {
+                [] if cfg!(windows) => vec![HostnameResolver::System],
+                [] => [hosts()?]
+                    .into_iter()
+                    .chain(mdns()?)
+                    .chain([HostnameResolver::Netbios(timeout)])
+                    .chain(dns())
+                    .collect(),
+                _ => [hosts()?].into_iter().chain(dns()).collect(),
+            });
+        }
+
+        options
+            .resolvers
+            .iter()
+            .map(|kind| {
+                let resolver = match kind {
+                    ResolverKind::System => {
+                        system_resolver_supported().then_some(HostnameResolver::System)
+                    }
+                    ResolverKind::Mdns => mdns()?,
+                    ResolverKind::Dns => dns(),
+                    ResolverKind::Netbios => Some(HostnameResolver::Netbios(timeout)),
+                    ResolverKind::Hosts => Some(hosts()?),
+                };
+                resolver.ok_or(Error::ResolverUnavailable(*kind))
+            })
+            .collect()
+    }
+}
+
+impl Resolver for HostnameResolver {
+    fn name(&self) -> &str {
+        match self {
+            HostnameResolver::System => "system",
+            HostnameResolver::Mdns(_) => "mdns",
+            HostnameResolver::Dns(_) => "dns",
+            HostnameResolver::Netbios(_) => "netbios",
+            HostnameResolver::Hosts(_) => "hosts",
+        }
+    }
+
+    fn resolve<'a>(&'a self, ip_addr: IpAddr, scope: Option<&'a str>) -> ResolveFuture<'a> {
+        Box::pin(async move {
+            match self {
+                HostnameResolver::Hosts(table) => table.lookup(&ip_addr).to_vec(),
+                HostnameResolver::System => resolve_hostname(&ip_addr).await,
+                HostnameResolver::Mdns(resolver) => resolver.resolve(&ip_addr, scope).await,
+                HostnameResolver::Dns(servers) => {
+                    dns::resolve_ptr(ip_addr, servers, dns::QUERY_TIMEOUT).await
+                }
+                HostnameResolver::Netbios(timeout) => netbios::node_status(ip_addr, *timeout)
+                    .await
+                    .and_then(|status| status.hostname())
+                    .into_iter()
+                    .collect(),
+            }
+        })
+    }
+
+    fn confirm<'a>(
+        &'a self,
+        name: &'a str,
+        ip_addr: IpAddr,
+        scope: Option<&'a str>,
+    ) -> ConfirmFuture<'a> {
+        Box::pin(async move {
+            match self {
+                HostnameResolver::Hosts(table) => Some(table.addresses(name).contains(&ip_addr)),
+                HostnameResolver::System => system_confirm(name, ip_addr).await,
+                HostnameResolver::Mdns(resolver) => Some(
+                    resolver
+                        .lookup(name, &ip_addr, scope)
+                        .await
+                        .contains(&ip_addr),
+                ),
+                HostnameResolver::Dns(servers) => {
+                    dns::resolve_addresses(name, ip_addr, servers, dns::QUERY_TIMEOUT)
+                        .await
+                        .map(|addresses| addresses.contains(&ip_addr))
+                }
+                // Without a WINS server, only the host itself could vouch for its name.
+                HostnameResolver::Netbios(_) => None,
+            }
+        })
+    }
+}
+
+/// Normalize a resolved hostname, ignoring empty and purely numeric answers.
+pub(crate) fn clean_hostname(ip_addr: &IpAddr, hostname: &str) -> Option<String> {
     let hostname = hostname.trim().trim_end_matches('.');
     if hostname.is_empty() || hostname == ip_addr.to_string() {
         return None;
     }
 
-    Some(format!("{}\t{}", ip_addr, hostname))
+    Some(hostname.to_string())
 }
 
 #[cfg(target_os = "linux")]
-fn parse_avahi_resolve_output(ip_addr: &IpAddr, output: &[u8]) -> Option<String> {
+fn parse_avahi_resolve_output(ip_addr: &IpAddr, output: &[u8]) -> Vec<String> {
     let output = String::from_utf8_lossy(output);
 
-    output.lines().find_map(|line| {
-        let mut parts = line.split_whitespace();
-        let ip = parts.next()?;
-        let hostname = parts.next()?;
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let ip = parts.next()?;
+            let hostname = parts.next()?;
 
-        if ip == ip_addr.to_string() {
-            format_hostname(ip_addr, hostname)
-        } else {
-            None
-        }
-    })
+            if ip == ip_addr.to_string() {
+                clean_hostname(ip_addr, hostname)
+            } else {
+                None
+            }
+        })
+        .collect()
 }
 
 #[cfg(target_os = "linux")]
-pub(crate) async fn resolve_hostname(ip_addr: &IpAddr) -> Option<String> {
-    let output = Command::new("avahi-resolve")
+async fn resolve_hostname(ip_addr: &IpAddr) -> Vec<String> {
+    let Ok(output) = Command::new("avahi-resolve")
         .arg("--address")
         .arg(ip_addr.to_string())
         .stderr(Stdio::null())
         .output()
         .await
-        .ok()?;
+    else {
+        return Vec::new();
+    };
 
-    if output.status.success() && !output.stdout.is_empty() {
+    if output.status.success() {
         parse_avahi_resolve_output(ip_addr, &output.stdout)
     } else {
-        None
+        Vec::new()
     }
 }
 
 #[cfg(windows)]
-pub(crate) async fn resolve_hostname(ip_addr: &IpAddr) -> Option<String> {
+async fn resolve_hostname(ip_addr: &IpAddr) -> Vec<String> {
     let ip_addr = *ip_addr;
-    let lookup = tokio::task::spawn_blocking(move || dns_lookup::lookup_addr(&ip_addr))
-        .await
-        .ok()?
-        .ok()?;
+    let lookup = tokio::task::spawn_blocking(move || dns_lookup::lookup_addr(&ip_addr)).await;
 
-    format_hostname(&ip_addr, &lookup)
+    match lookup {
+        Ok(Ok(hostname)) => clean_hostname(&ip_addr, &hostname).into_iter().collect(),
+        _ => Vec::new(),
+    }
 }
 
 #[cfg(not(any(target_os = "linux", windows)))]
-pub(crate) async fn resolve_hostname(_ip_addr: &IpAddr) -> Option<String> {
-    None
+async fn resolve_hostname(_ip_addr: &IpAddr) -> Vec<String> {
+    Vec::new()
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -230,16 +535,45 @@ fn current_ping_platform() -> PingPlatform {
     PingPlatform::OtherUnix
 }
 
-fn system_ping_args(platform: PingPlatform, ip_addr: &IpAddr, timeout: usize) -> Vec<String> {
+/// A timeout in milliseconds, for Windows `ping /w` and macOS `ping -W`.
+fn timeout_millis(timeout: Duration) -> String {
+    timeout.as_millis().max(1).to_string()
+}
+
+/// A timeout in seconds with millisecond precision, e.g. `0.25`, for iputils
+/// `ping -W`.
+fn timeout_seconds(timeout: Duration) -> String {
+    let millis = timeout.as_millis().max(1);
+    match millis % 1000 {
+        0 => (millis / 1000).to_string(),
+        fraction => format!("{}.{:03}", millis / 1000, fraction)
+            .trim_end_matches('0')
+            .to_string(),
+    }
+}
+
+/// A timeout rounded up to whole seconds, for `ping` options that take no
+/// fractions.
+fn timeout_whole_seconds(timeout: Duration) -> String {
+    let seconds = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+    seconds.max(1).to_string()
+}
+
+fn system_ping_args(platform: PingPlatform, ip_addr: &IpAddr, timeout: Duration) -> Vec<String> {
     match platform {
         PingPlatform::Windows => vec![
             "/n".to_string(),
             "1".to_string(),
             "/w".to_string(),
-            timeout.saturating_mul(1000).to_string(),
+            timeout_millis(timeout),
             ip_addr.to_string(),
         ],
         PingPlatform::Linux | PingPlatform::OtherUnix => {
+            // Only iputils is known to take fractional seconds.
+            let timeout = match platform {
+                PingPlatform::Linux => timeout_seconds(timeout),
+                _ => timeout_whole_seconds(timeout),
+            };
             let mut args = Vec::new();
             if ip_addr.is_ipv6() {
                 args.push("-6".to_string());
@@ -248,7 +582,7 @@ fn system_ping_args(platform: PingPlatform, ip_addr: &IpAddr, timeout: usize) ->
                 "-c".to_string(),
                 "1".to_string(),
                 "-W".to_string(),
-                timeout.to_string(),
+                timeout,
                 ip_addr.to_string(),
             ]);
             args
@@ -257,7 +591,7 @@ fn system_ping_args(platform: PingPlatform, ip_addr: &IpAddr, timeout: usize) ->
             "-c".to_string(),
             "1".to_string(),
             "-W".to_string(),
-            timeout.saturating_mul(1000).to_string(),
+            timeout_millis(timeout),
             ip_addr.to_string(),
         ],
     }
@@ -271,25 +605,55 @@ fn system_ping_command(platform: PingPlatform, ip_addr: &IpAddr) -> &'static str
     }
 }
 
+/// Find the round-trip time in a line of `ping` output.
+///
+/// Matches `time=0.045 ms` (Linux, macOS), `time=12ms` and `time<1ms`
+/// (Windows), and localized labels such as `Zeit=1ms`.
+fn parse_ping_rtt(line: &str) -> Option<Duration> {
+    let mut words = line.split_whitespace().peekable();
+    while let Some(word) = words.next() {
+        let Some(position) = word.find(['=', '<']) else {
+            continue;
+        };
+        let value = &word[position + 1..];
+        let millis = match value.strip_suffix("ms") {
+            Some(millis) => millis,
+            None if words.peek() == Some(&"ms") => value,
+            None => continue,
+        };
+        if let Ok(millis) = millis.parse::<f64>()
+            && millis.is_finite()
+            && millis >= 0.0
+        {
+            return Some(Duration::from_secs_f64(millis / 1000.0));
+        }
+    }
+
+    None
+}
+
 /// Ping using system `ping` command.
-pub(crate) async fn system_ping(ip_addr: &IpAddr, timeout: usize) -> bool {
+///
+/// Fails only if `ping` could not be started.
+pub(crate) async fn system_ping(ip_addr: &IpAddr, timeout: Duration) -> io::Result<Option<Reply>> {
     let platform = current_ping_platform();
     let args = system_ping_args(platform, ip_addr, timeout);
-    let mut command = match Command::new(system_ping_command(platform, ip_addr))
+    let output = Command::new(system_ping_command(platform, ip_addr))
         .args(args)
-        .stdout(Stdio::null())
         .stderr(Stdio::null())
-        .spawn()
-    {
-        Ok(cmd) => cmd,
-        Err(_) => return false, // If we can't spawn ping, consider it failed
-    };
+        .output()
+        .await?;
 
     // Check if the ping succeeded.
-    match command.wait().await {
-        Ok(status) => status.success(),
-        Err(_) => false,
+    if !output.status.success() {
+        return Ok(None);
     }
+
+    let output = String::from_utf8_lossy(&output.stdout);
+    Ok(Some(Reply {
+        rtt: output.lines().find_map(parse_ping_rtt),
+        open_ports: Vec::new(),
+    }))
 }
 
 fn scoped_ipv6_multicast_target(
@@ -318,7 +682,7 @@ fn system_ipv6_multicast_ping_args(
     platform: PingPlatform,
     interface: &str,
     index: Option<u32>,
-    timeout: usize,
+    timeout: Duration,
 ) -> Vec<String> {
     let target = scoped_ipv6_multicast_target(platform, interface, index);
 
@@ -327,20 +691,21 @@ fn system_ipv6_multicast_ping_args(
             "/n".to_string(),
             "1".to_string(),
             "/w".to_string(),
-            timeout.saturating_mul(1000).to_string(),
+            timeout_millis(timeout),
             target,
         ],
         PingPlatform::Linux | PingPlatform::OtherUnix => vec![
             "-6".to_string(),
             "-w".to_string(),
-            timeout.to_string(),
+            // Older iputils only take whole seconds for the deadline.
+            timeout_whole_seconds(timeout),
             target,
         ],
         PingPlatform::Macos => vec![
             "-c".to_string(),
             "1".to_string(),
             "-W".to_string(),
-            timeout.saturating_mul(1000).to_string(),
+            timeout_millis(timeout),
             target,
         ],
     }
@@ -365,21 +730,24 @@ fn parse_ping_reply_addresses(output: &[u8]) -> Vec<DiscoveredAddress> {
                         candidate = stripped;
                     }
 
-                    let display_addr = candidate.to_string();
-                    let ip_addr = candidate.split_once('%').map_or(candidate, |(ip, _)| ip);
+                    let (ip_addr, scope) = match candidate.split_once('%') {
+                        Some((ip_addr, scope)) => (ip_addr, Some(scope.to_string())),
+                        None => (candidate, None),
+                    };
                     return ip_addr
                         .parse::<IpAddr>()
                         .ok()
                         .map(|ip_addr| DiscoveredAddress {
                             ip_addr,
-                            display_addr,
+                            scope,
+                            rtt: parse_ping_rtt(line),
                         });
                 }
             }
 
             None
         })
-        .filter(|address| seen.insert(address.display_addr.clone()))
+        .filter(|address| seen.insert((address.ip_addr, address.scope.clone())))
         .collect()
 }
 
@@ -387,7 +755,7 @@ fn parse_ping_reply_addresses(output: &[u8]) -> Vec<DiscoveredAddress> {
 pub(crate) async fn system_ipv6_multicast_ping(
     interface: &str,
     index: Option<u32>,
-    timeout: usize,
+    timeout: Duration,
 ) -> Vec<DiscoveredAddress> {
     let platform = current_ping_platform();
     let args = system_ipv6_multicast_ping_args(platform, interface, index, timeout);
@@ -416,15 +784,11 @@ fn scoped_ipv6_multicast_socket_addr(index: Option<u32>) -> Option<SocketAddr> {
 }
 
 #[cfg(unix)]
-fn discovered_ipv6_reply(interface: &str, ip_addr: IpAddr) -> DiscoveredAddress {
-    let display_addr = match ip_addr {
-        IpAddr::V6(address) => format!("{}%{}", address, interface),
-        IpAddr::V4(address) => address.to_string(),
-    };
-
+fn discovered_ipv6_reply(interface: &str, ip_addr: IpAddr, rtt: Duration) -> DiscoveredAddress {
     DiscoveredAddress {
         ip_addr,
-        display_addr,
+        scope: ip_addr.is_ipv6().then(|| interface.to_string()),
+        rtt: Some(rtt),
     }
 }
 
@@ -433,15 +797,23 @@ pub(crate) async fn socket_ipv6_multicast_ping(
     interface: &str,
     index: Option<u32>,
     source: Ipv6Addr,
-    timeout: usize,
+    timeout: Duration,
     ping_backend: PingBackend,
-) -> Result<Vec<DiscoveredAddress>, ()> {
-    let target = scoped_ipv6_multicast_socket_addr(index).ok_or(())?;
+    sequence: u16,
+) -> Result<Vec<DiscoveredAddress>, Error> {
+    let target = scoped_ipv6_multicast_socket_addr(index)
+        .ok_or_else(|| Error::InterfaceNotFound(interface.to_string()))?;
     let socket_type = match ping_backend {
         PingBackend::RawSocket => SocketType::Raw,
-        PingBackend::System => SocketType::Dgram,
+        PingBackend::System
+        | PingBackend::Datagram
+        | PingBackend::Arp
+        | PingBackend::Tcp
+        | PingBackend::Udp => SocketType::Dgram,
     };
-    let mut pinger = Pinger::with_socket_addr(target, socket_type).map_err(|_| ())?;
+    let context = || format!("IPv6 multicast ping on {} failed", interface);
+    let ping_error = |e| ping_error(socket_type, context(), e);
+    let mut pinger = Pinger::with_socket_addr(target, socket_type).map_err(ping_error)?;
     pinger
         .bind_source(SocketAddr::V6(SocketAddrV6::new(
             source,
@@ -449,13 +821,13 @@ pub(crate) async fn socket_ipv6_multicast_ping(
             0,
             index.unwrap_or(0),
         )))
-        .map_err(|_| ())?;
-    pinger.timeout(Duration::from_secs(timeout as u64));
+        .map_err(ping_error)?;
+    pinger.timeout(timeout);
 
-    let replies = pinger.ping_replies(0).await.map_err(|_| ())?;
+    let replies = pinger.ping_replies(sequence).await.map_err(ping_error)?;
     Ok(replies
         .into_iter()
-        .map(|result| discovered_ipv6_reply(interface, result.reply.source))
+        .map(|result| discovered_ipv6_reply(interface, result.reply.source, result.rtt))
         .collect())
 }
 
@@ -464,33 +836,56 @@ pub(crate) async fn socket_ipv6_multicast_ping(
     _interface: &str,
     _index: Option<u32>,
     _source: Ipv6Addr,
-    _timeout: usize,
+    _timeout: Duration,
     _ping_backend: PingBackend,
-) -> Result<Vec<DiscoveredAddress>, ()> {
-    Err(())
+    _sequence: u16,
+) -> Result<Vec<DiscoveredAddress>, Error> {
+    Err(Error::Unsupported("ICMP sockets"))
 }
 
+/// Wrap an error of `tiny_ping`, reporting permission errors for the kind of
+/// socket that was used.
 #[cfg(unix)]
-pub(crate) async fn socket_ping(ip_addr: &IpAddr, source: Option<IpAddr>, timeout: usize) -> bool {
-    if let Ok(mut pinger) = Pinger::new(*ip_addr) {
-        if let Some(source) = source
-            && pinger.bind_source(SocketAddr::new(source, 0)).is_err()
-        {
-            return false;
-        }
-        pinger.timeout(Duration::from_secs(timeout as u64));
-        return pinger.ping(0).await.is_ok();
+fn ping_error(socket_type: SocketType, context: String, error: tiny_ping::Error) -> Error {
+    let denied = match socket_type {
+        SocketType::Raw => Error::RawSocketPermission,
+        SocketType::Dgram => Error::DatagramSocketPermission,
+    };
+    match error {
+        tiny_ping::Error::Io(e) => Error::socket(denied, context, e),
+        e => Error::Io {
+            context,
+            source: std::io::Error::other(e.to_string()),
+        },
     }
-    false
+}
+
+/// Check that raw ICMP sockets can be opened, so a raw sweep does not fail
+/// silently host by host.
+#[cfg(unix)]
+pub(crate) fn check_raw_socket() -> Result<(), Error> {
+    Pinger::new(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        .map(|_| ())
+        .map_err(|e| ping_error(SocketType::Raw, "failed to open raw socket".to_string(), e))
 }
 
 #[cfg(not(unix))]
-pub(crate) async fn socket_ping(
-    _ip_addr: &IpAddr,
-    _source: Option<IpAddr>,
-    _timeout: usize,
-) -> bool {
-    false
+pub(crate) fn check_raw_socket() -> Result<(), Error> {
+    Err(Error::Unsupported("raw sockets"))
+}
+
+/// Check that ICMP datagram sockets can be opened, which on Linux depends on
+/// `net.ipv4.ping_group_range`.
+pub(crate) fn check_datagram_socket() -> Result<(), Error> {
+    icmp::open_socket(false, None, socket2::Type::DGRAM)
+        .map(|_| ())
+        .map_err(|e| {
+            Error::socket(
+                Error::DatagramSocketPermission,
+                "failed to open ICMP datagram socket",
+                e,
+            )
+        })
 }
 
 #[cfg(unix)]
@@ -511,18 +906,20 @@ pub async fn can_open_raw_socket() -> bool {
 #[cfg(test)]
 mod tests {
     use super::{
-        PingBackend, PingPlatform, RuntimePlatform, format_hostname, parse_ping_reply_addresses,
-        scoped_ipv6_multicast_target, select_ping_backend_for, system_ipv6_multicast_ping_args,
-        system_ping_args,
+        PingBackend, PingPlatform, RuntimePlatform, clean_hostname, parse_ping_reply_addresses,
+        parse_ping_rtt, scoped_ipv6_multicast_target, select_ping_backend_for,
+        system_ipv6_multicast_ping_args, system_ping_args,
     };
+    use crate::Error;
     use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::time::Duration;
 
     #[test]
     fn windows_ping_args_use_count_and_millisecond_timeout() {
         let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1));
 
         assert_eq!(
-            system_ping_args(PingPlatform::Windows, &ip, 1),
+            system_ping_args(PingPlatform::Windows, &ip, Duration::from_secs(1)),
             vec!["/n", "1", "/w", "1000", "192.168.1.1"]
         );
     }
@@ -532,7 +929,7 @@ mod tests {
         let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1));
 
         assert_eq!(
-            system_ping_args(PingPlatform::Linux, &ip, 1),
+            system_ping_args(PingPlatform::Linux, &ip, Duration::from_secs(1)),
             vec!["-c", "1", "-W", "1", "192.168.1.1"]
         );
     }
@@ -542,7 +939,7 @@ mod tests {
         let ip = IpAddr::V6(Ipv6Addr::LOCALHOST);
 
         assert_eq!(
-            system_ping_args(PingPlatform::Linux, &ip, 1),
+            system_ping_args(PingPlatform::Linux, &ip, Duration::from_secs(1)),
             vec!["-6", "-c", "1", "-W", "1", "::1"]
         );
     }
@@ -552,11 +949,39 @@ mod tests {
         let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1));
 
         assert_eq!(
-            system_ping_args(PingPlatform::Macos, &ip, 1),
+            system_ping_args(PingPlatform::Macos, &ip, Duration::from_secs(1)),
             vec!["-c", "1", "-W", "1000", "192.168.1.1"]
         );
     }
 
+    #[test]
+    fn ping_args_keep_sub_second_timeouts() {
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1));
+        let timeout = Duration::from_millis(250);
+
+        assert_eq!(
+            system_ping_args(PingPlatform::Linux, &ip, timeout)[3],
+            "0.25"
+        );
+        assert_eq!(
+            system_ping_args(PingPlatform::Linux, &ip, Duration::from_millis(1500))[3],
+            "1.5"
+        );
+        assert_eq!(
+            system_ping_args(PingPlatform::Windows, &ip, timeout)[3],
+            "250"
+        );
+        assert_eq!(
+            system_ping_args(PingPlatform::Macos, &ip, timeout)[3],
+            "250"
+        );
+        // Other platforms' `ping -W` may not take fractions, so round up.
+        assert_eq!(
+            system_ping_args(PingPlatform::OtherUnix, &ip, timeout)[3],
+            "1"
+        );
+    }
+
     #[test]
     fn ipv6_multicast_target_uses_windows_interface_index_when_available() {
         assert_eq!(
@@ -568,7 +993,12 @@ mod tests {
     #[test]
     fn linux_ipv6_multicast_ping_args_use_scoped_all_nodes_address() {
         assert_eq!(
-            system_ipv6_multicast_ping_args(PingPlatform::Linux, "eth0", Some(2), 1),
+            system_ipv6_multicast_ping_args(
+                PingPlatform::Linux,
+                "eth0",
+                Some(2),
+                Duration::from_secs(1)
+            ),
             vec!["-6", "-w", "1", "ff02::1%eth0"]
         );
     }
@@ -589,18 +1019,36 @@ mod tests {
 
     #[cfg(unix)]
     #[test]
-    fn socket_ipv6_replies_keep_interface_scope_in_display_addr() {
-        let address = super::discovered_ipv6_reply("eth0", IpAddr::V6("fe80::1".parse().unwrap()));
+    fn socket_ipv6_replies_keep_interface_scope() {
+        let rtt = std::time::Duration::from_millis(3);
+        let address =
+            super::discovered_ipv6_reply("eth0", IpAddr::V6("fe80::1".parse().unwrap()), rtt);
 
         assert_eq!(
             address,
             super::DiscoveredAddress {
                 ip_addr: IpAddr::V6("fe80::1".parse().unwrap()),
-                display_addr: "fe80::1%eth0".to_string(),
+                scope: Some("eth0".to_string()),
+                rtt: Some(rtt),
             }
         );
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn permission_errors_name_the_socket_that_was_denied() {
+        let denied = || tiny_ping::Error::Io(std::io::ErrorKind::PermissionDenied.into());
+
+        assert!(matches!(
+            super::ping_error(tiny_ping::SocketType::Dgram, "ping".to_string(), denied()),
+            Error::DatagramSocketPermission(_)
+        ));
+        assert!(matches!(
+            super::ping_error(tiny_ping::SocketType::Raw, "ping".to_string(), denied()),
+            Error::RawSocketPermission(_)
+        ));
+    }
+
     #[test]
     fn ping_backend_variants_stay_distinct() {
         assert_ne!(PingBackend::System, PingBackend::RawSocket);
@@ -609,39 +1057,69 @@ mod tests {
     #[test]
     fn unix_backend_uses_raw_socket_when_requested_or_ping_missing() {
         assert_eq!(
-            select_ping_backend_for(RuntimePlatform::Unix, true, true),
-            Ok(PingBackend::RawSocket)
+            select_ping_backend_for(RuntimePlatform::Unix, true, true, false).ok(),
+            Some(PingBackend::RawSocket)
         );
         assert_eq!(
-            select_ping_backend_for(RuntimePlatform::Unix, false, false),
-            Ok(PingBackend::RawSocket)
+            select_ping_backend_for(RuntimePlatform::Unix, false, false, false).ok(),
+            Some(PingBackend::RawSocket)
         );
         assert_eq!(
-            select_ping_backend_for(RuntimePlatform::Unix, false, true),
-            Ok(PingBackend::System)
+            select_ping_backend_for(RuntimePlatform::Unix, false, true, false).ok(),
+            Some(PingBackend::System)
         );
     }
 
+    #[test]
+    fn unix_backend_prefers_permitted_datagram_sockets_over_system_ping() {
+        assert_eq!(
+            select_ping_backend_for(RuntimePlatform::Unix, false, true, true).ok(),
+            Some(PingBackend::Datagram)
+        );
+        assert_eq!(
+            select_ping_backend_for(RuntimePlatform::Unix, true, true, true).ok(),
+            Some(PingBackend::RawSocket)
+        );
+        assert_eq!(
+            select_ping_backend_for(RuntimePlatform::NonUnix, false, true, true).ok(),
+            Some(PingBackend::System)
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn ping_group_ranges_parse_as_inclusive_bounds() {
+        assert_eq!(
+            super::parse_ping_group_range("0\t2147483647\n"),
+            Some((0, 2147483647))
+        );
+        assert_eq!(super::parse_ping_group_range("1 0"), Some((1, 0)));
+        assert_eq!(super::parse_ping_group_range("1"), None);
+    }
+
     #[test]
     fn non_unix_backend_uses_system_ping_even_when_raw_requested() {
         assert_eq!(
-            select_ping_backend_for(RuntimePlatform::NonUnix, true, true),
-            Ok(PingBackend::System)
+            select_ping_backend_for(RuntimePlatform::NonUnix, true, true, false).ok(),
+            Some(PingBackend::System)
         );
     }
 
     #[test]
     fn non_unix_backend_errors_when_system_ping_is_missing() {
-        assert!(select_ping_backend_for(RuntimePlatform::NonUnix, false, false).is_err());
+        assert!(matches!(
+            select_ping_backend_for(RuntimePlatform::NonUnix, false, false, false),
+            Err(Error::PingNotFound)
+        ));
     }
 
     #[test]
-    fn hostname_output_matches_existing_ip_hostname_format() {
+    fn hostnames_drop_the_trailing_root_label() {
         let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10));
 
         assert_eq!(
-            format_hostname(&ip, "printer.local."),
-            Some("192.168.1.10\tprinter.local".to_string())
+            clean_hostname(&ip, "printer.local."),
+            Some("printer.local".to_string())
         );
     }
 
@@ -649,8 +1127,8 @@ mod tests {
     fn hostname_output_ignores_empty_and_numeric_names() {
         let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10));
 
-        assert_eq!(format_hostname(&ip, ""), None);
-        assert_eq!(format_hostname(&ip, "192.168.1.10"), None);
+        assert_eq!(clean_hostname(&ip, ""), None);
+        assert_eq!(clean_hostname(&ip, "192.168.1.10"), None);
     }
 
     #[cfg(target_os = "linux")]
@@ -660,7 +1138,14 @@ mod tests {
 
         assert_eq!(
             super::parse_avahi_resolve_output(&ip, b"192.168.1.10\tprinter.local\n"),
-            Some("192.168.1.10\tprinter.local".to_string())
+            vec!["printer.local".to_string()]
+        );
+        assert_eq!(
+            super::parse_avahi_resolve_output(
+                &ip,
+                b"192.168.1.10\tprinter.local\n192.168.1.10\tprinter-2.local\n192.168.1.11\tnas.local\n"
+            ),
+            vec!["printer.local".to_string(), "printer-2.local".to_string()]
         );
     }
 
@@ -674,7 +1159,8 @@ mod tests {
             replies,
             vec![super::DiscoveredAddress {
                 ip_addr: IpAddr::V6("fe80::5054:ff:fe12:3456".parse::<Ipv6Addr>().unwrap()),
-                display_addr: "fe80::5054:ff:fe12:3456%eth0".to_string(),
+                scope: Some("eth0".to_string()),
+                rtt: Some(Duration::from_micros(100)),
             }]
         );
     }
@@ -687,8 +1173,26 @@ mod tests {
             replies,
             vec![super::DiscoveredAddress {
                 ip_addr: IpAddr::V6("fe80::1".parse().unwrap()),
-                display_addr: "fe80::1%12".to_string(),
+                scope: Some("12".to_string()),
+                rtt: Some(Duration::from_millis(1)),
             }]
         );
     }
+
+    #[test]
+    fn ping_rtt_parser_reads_unix_and_windows_output() {
+        assert_eq!(
+            parse_ping_rtt("64 bytes from 192.168.1.1: icmp_seq=1 ttl=64 time=0.045 ms"),
+            Some(Duration::from_micros(45))
+        );
+        assert_eq!(
+            parse_ping_rtt("Reply from 192.168.1.1: bytes=32 time=12ms TTL=128"),
+            Some(Duration::from_millis(12))
+        );
+        assert_eq!(
+            parse_ping_rtt("Antwort von 192.168.1.1: Bytes=32 Zeit<1ms TTL=128"),
+            Some(Duration::from_millis(1))
+        );
+        assert_eq!(parse_ping_rtt("1 packets transmitted, 1 received"), None);
+    }
 }
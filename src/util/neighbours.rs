@@ -0,0 +1,455 @@
+//! Hardware addresses from the kernel neighbour table.
+//!
+//! IPv4 entries come from `/proc/net/arp` and IPv6 entries from a netlink
+//! `RTM_GETNEIGH` dump. Other platforms have an empty table.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant};
+
+use crate::MacAddr;
+
+/// `ATF_COM`: the entry has a resolved hardware address.
+const ATF_COM: u32 = 0x2;
+const NLMSG_HEADER_LEN: usize = 16;
+const NDMSG_LEN: usize = 12;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const RTM_NEWNEIGH: u16 = 28;
+const NDA_DST: u16 = 1;
+const NDA_LLADDR: u16 = 2;
+const NUD_INCOMPLETE: u16 = 0x01;
+const NUD_FAILED: u16 = 0x20;
+/// How old the table must be before a missing address re-reads it.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Neighbour {
+    ip_addr: IpAddr,
+    mac: MacAddr,
+    interface: String,
+}
+
+/// A snapshot of the kernel neighbour table.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct NeighbourTable {
+    neighbours: Vec<Neighbour>,
+    /// When the table was read, or `None` if it never was.
+    read_at: Option<Instant>,
+}
+
+impl NeighbourTable {
+    /// Read the current neighbour table. Entries that cannot be read are skipped.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn read() -> Self {
+        let mut neighbours = std::fs::read_to_string("/proc/net/arp")
+            .map(|contents| parse_proc_arp(&contents))
+            .unwrap_or_default();
+        neighbours.extend(linux::ipv6_neighbours().unwrap_or_default());
+
+        Self {
+            neighbours,
+            read_at: Some(Instant::now()),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(crate) fn read() -> Self {
+        Self {
+            read_at: Some(Instant::now()),
+            ..Self::default()
+        }
+    }
+
+    /// Hardware address of `ip_addr`, preferring an entry on `interface`.
+    pub(crate) fn lookup(&self, ip_addr: IpAddr, interface: Option<&str>) -> Option<MacAddr> {
+        let mut matches = self
+            .neighbours
+            .iter()
+            .filter(|neighbour| neighbour.ip_addr == ip_addr);
+        let first = matches.clone().next()?;
+        matches
+            .find(|neighbour| Some(neighbour.interface.as_str()) == interface)
+            .or(Some(first))
+            .map(|neighbour| neighbour.mac)
+    }
+
+    /// Like [`NeighbourTable::lookup`], but re-reads the table when an address
+    /// is missing, since hosts found after the last read are likely new. Misses
+    /// within [`REFRESH_INTERVAL`] of that read keep the snapshot, so a sweep
+    /// with many hosts lacking an entry does not read the table for each one.
+    pub(crate) fn lookup_or_refresh(
+        &mut self,
+        ip_addr: IpAddr,
+        interface: Option<&str>,
+    ) -> Option<MacAddr> {
+        self.lookup_or_refresh_with(ip_addr, interface, Instant::now(), Self::read)
+    }
+
+    fn lookup_or_refresh_with(
+        &mut self,
+        ip_addr: IpAddr,
+        interface: Option<&str>,
+        now: Instant,
+        read: impl FnOnce() -> Self,
+    ) -> Option<MacAddr> {
+        self.lookup(ip_addr, interface).or_else(|| {
+            if self
+                .read_at
+                .is_some_and(|read_at| now.saturating_duration_since(read_at) < REFRESH_INTERVAL)
+            {
+                return None;
+            }
+            *self = Self {
+                read_at: Some(now),
+                ..read()
+            };
+            self.lookup(ip_addr, interface)
+        })
+    }
+}
+
+/// Parse the complete entries of `/proc/net/arp`.
+fn parse_proc_arp(contents: &str) -> Vec<Neighbour> {
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [ip_addr, _hw_type, flags, mac, _mask, interface] = fields[..] else {
+                return None;
+            };
+            let flags = u32::from_str_radix(flags.trim_start_matches("0x"), 16).ok()?;
+            let mac: MacAddr = mac.parse().ok()?;
+            if flags & ATF_COM == 0 || mac.octets() == [0; 6] {
+                return None;
+            }
+
+            Some(Neighbour {
+                ip_addr: ip_addr.parse().ok()?,
+                mac,
+                interface: interface.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// A neighbour from a netlink dump, with its interface index.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct NetlinkNeighbour {
+    ip_addr: IpAddr,
+    mac: MacAddr,
+    index: u32,
+}
+
+fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Parse one buffer of netlink messages into `neighbours`.
+///
+/// Returns `true` once the end of the dump, or an error, has been reached.
+fn parse_netlink_neighbours(buffer: &[u8], neighbours: &mut Vec<NetlinkNeighbour>) -> bool {
+    let u16_at = |offset: usize| u16::from_ne_bytes([buffer[offset], buffer[offset + 1]]);
+    let u32_at = |offset: usize| {
+        u32::from_ne_bytes([
+            buffer[offset],
+            buffer[offset + 1],
+            buffer[offset + 2],
+            buffer[offset + 3],
+        ])
+    };
+
+    let mut offset = 0;
+    while offset + NLMSG_HEADER_LEN <= buffer.len() {
+        let len = u32_at(offset) as usize;
+        if len < NLMSG_HEADER_LEN || offset + len > buffer.len() {
+            return true;
+        }
+        match u16_at(offset + 4) {
+            NLMSG_DONE | NLMSG_ERROR => return true,
+            RTM_NEWNEIGH if len >= NLMSG_HEADER_LEN + NDMSG_LEN => {
+                let ndmsg = offset + NLMSG_HEADER_LEN;
+                let index = u32_at(ndmsg + 4);
+                let state = u16_at(ndmsg + 8);
+
+                let mut ip_addr = None;
+                let mut mac = None;
+                let mut attribute = ndmsg + NDMSG_LEN;
+                while attribute + 4 <= offset + len {
+                    let attribute_len = u16_at(attribute) as usize;
+                    if attribute_len < 4 || attribute + attribute_len > offset + len {
+                        break;
+                    }
+                    let payload = &buffer[attribute + 4..attribute + attribute_len];
+                    match u16_at(attribute + 2) {
+                        NDA_DST => {
+                            ip_addr = match payload.len() {
+                                4 => <[u8; 4]>::try_from(payload)
+                                    .ok()
+                                    .map(|octets| IpAddr::V4(Ipv4Addr::from(octets))),
+                                16 => <[u8; 16]>::try_from(payload)
+                                    .ok()
+                                    .map(|octets| IpAddr::V6(Ipv6Addr::from(octets))),
+                                _ => None,
+                            }
+                        }
+                        NDA_LLADDR => mac = <[u8; 6]>::try_from(payload).ok().map(MacAddr::new),
+                        _ => {}
+                    }
+                    attribute += align4(attribute_len);
+                }
+
+                if let (Some(ip_addr), Some(mac)) = (ip_addr, mac)
+                    && state & (NUD_INCOMPLETE | NUD_FAILED) == 0
+                    && mac.octets() != [0; 6]
+                {
+                    neighbours.push(NetlinkNeighbour {
+                        ip_addr,
+                        mac,
+                        index,
+                    });
+                }
+            }
+            _ => {}
+        }
+        offset += align4(len);
+    }
+
+    false
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::CStr;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    use super::{NDMSG_LEN, NLMSG_HEADER_LEN, Neighbour, parse_netlink_neighbours};
+
+    fn interface_name(index: u32) -> Option<String> {
+        let mut buffer = [0; libc::IF_NAMESIZE];
+        // SAFETY: `buffer` has room for the `IF_NAMESIZE` bytes `if_indextoname` may write.
+        let name = unsafe { libc::if_indextoname(index, buffer.as_mut_ptr()) };
+        if name.is_null() {
+            return None;
+        }
+        // SAFETY: on success `name` points to a NUL-terminated string in our buffer.
+        let name = unsafe { CStr::from_ptr(name) };
+        Some(name.to_string_lossy().into_owned())
+    }
+
+    /// Dump the IPv6 neighbour cache over a netlink route socket.
+    pub(super) fn ipv6_neighbours() -> io::Result<Vec<Neighbour>> {
+        // SAFETY: plain socket(2) call; the result is checked before use.
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `fd` is a freshly opened descriptor that nothing else owns.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut request = [0u8; NLMSG_HEADER_LEN + NDMSG_LEN];
+        request[0..4].copy_from_slice(&((NLMSG_HEADER_LEN + NDMSG_LEN) as u32).to_ne_bytes());
+        request[4..6].copy_from_slice(&libc::RTM_GETNEIGH.to_ne_bytes());
+        let flags = (libc::NLM_F_REQUEST | libc::NLM_F_DUMP) as u16;
+        request[6..8].copy_from_slice(&flags.to_ne_bytes());
+        request[8..12].copy_from_slice(&1u32.to_ne_bytes());
+        request[NLMSG_HEADER_LEN] = libc::AF_INET6 as u8;
+
+        // SAFETY: `sockaddr_nl` is plain old data, so all zeroes is a valid value.
+        let mut kernel: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        kernel.nl_family = libc::AF_NETLINK as u16;
+        // SAFETY: `request` and `kernel` are valid for the lengths passed.
+        let sent = unsafe {
+            libc::sendto(
+                fd.as_raw_fd(),
+                request.as_ptr().cast(),
+                request.len(),
+                0,
+                (&kernel as *const libc::sockaddr_nl).cast(),
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut neighbours = Vec::new();
+        let mut buffer = vec![0u8; 32 * 1024];
+        loop {
+            // SAFETY: `buffer` is valid for writes of `buffer.len()` bytes.
+            let received =
+                unsafe { libc::recv(fd.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len(), 0) };
+            if received < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if received == 0
+                || parse_netlink_neighbours(&buffer[..received as usize], &mut neighbours)
+            {
+                break;
+            }
+        }
+
+        Ok(neighbours
+            .into_iter()
+            .filter_map(|neighbour| {
+                Some(Neighbour {
+                    ip_addr: neighbour.ip_addr,
+                    mac: neighbour.mac,
+                    interface: interface_name(neighbour.index)?,
+                })
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use std::time::Instant;
+
+    use super::{
+        NLMSG_DONE, NUD_INCOMPLETE, Neighbour, NeighbourTable, NetlinkNeighbour, REFRESH_INTERVAL,
+        RTM_NEWNEIGH, parse_netlink_neighbours, parse_proc_arp,
+    };
+    use crate::MacAddr;
+
+    const PROC_NET_ARP: &str = "\
+IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.1      0x1         0x2         a4:2b:b0:11:22:33     *        wlan0
+192.168.1.50     0x1         0x0         00:00:00:00:00:00     *        wlan0
+10.0.0.7         0x1         0x6         3c:52:82:aa:bb:cc     *        eth0
+";
+
+    fn neighbour_message(ip_addr: IpAddr, mac: [u8; 6], index: u32, state: u16) -> Vec<u8> {
+        let dst = match ip_addr {
+            IpAddr::V4(ip_addr) => ip_addr.octets().to_vec(),
+            IpAddr::V6(ip_addr) => ip_addr.octets().to_vec(),
+        };
+        let mut message = vec![0; 16];
+        message[4..6].copy_from_slice(&RTM_NEWNEIGH.to_ne_bytes());
+        let mut ndmsg = [0u8; 12];
+        ndmsg[0] = if ip_addr.is_ipv4() { 2 } else { 10 };
+        ndmsg[4..8].copy_from_slice(&index.to_ne_bytes());
+        ndmsg[8..10].copy_from_slice(&state.to_ne_bytes());
+        message.extend_from_slice(&ndmsg);
+        for (kind, payload) in [(1u16, dst), (2u16, mac.to_vec())] {
+            message.extend_from_slice(&(payload.len() as u16 + 4).to_ne_bytes());
+            message.extend_from_slice(&kind.to_ne_bytes());
+            message.extend_from_slice(&payload);
+            message.resize(message.len().next_multiple_of(4), 0);
+        }
+        let len = message.len() as u32;
+        message[0..4].copy_from_slice(&len.to_ne_bytes());
+        message
+    }
+
+    #[test]
+    fn proc_net_arp_keeps_only_complete_entries() {
+        assert_eq!(
+            parse_proc_arp(PROC_NET_ARP),
+            vec![
+                Neighbour {
+                    ip_addr: "192.168.1.1".parse().unwrap(),
+                    mac: "a4:2b:b0:11:22:33".parse().unwrap(),
+                    interface: "wlan0".to_string(),
+                },
+                Neighbour {
+                    ip_addr: "10.0.0.7".parse().unwrap(),
+                    mac: "3c:52:82:aa:bb:cc".parse().unwrap(),
+                    interface: "eth0".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn netlink_dumps_yield_reachable_neighbours() {
+        let mac = [0x02, 0, 0, 0, 0, 9];
+        let mut buffer = neighbour_message("fe80::9".parse().unwrap(), mac, 3, 0x02);
+        buffer.extend(neighbour_message(
+            "fe80::a".parse().unwrap(),
+            [0; 6],
+            3,
+            NUD_INCOMPLETE,
+        ));
+        let mut neighbours = Vec::new();
+
+        assert!(!parse_netlink_neighbours(&buffer, &mut neighbours));
+        assert_eq!(
+            neighbours,
+            vec![NetlinkNeighbour {
+                ip_addr: "fe80::9".parse().unwrap(),
+                mac: MacAddr::new(mac),
+                index: 3,
+            }]
+        );
+
+        let mut done = vec![0; 20];
+        done[0..4].copy_from_slice(&20u32.to_ne_bytes());
+        done[4..6].copy_from_slice(&NLMSG_DONE.to_ne_bytes());
+        assert!(parse_netlink_neighbours(&done, &mut neighbours));
+    }
+
+    #[test]
+    fn lookups_prefer_the_matching_interface() {
+        let mac = |last| MacAddr::new([0x02, 0, 0, 0, 0, last]);
+        let ip_addr: IpAddr = "fe80::1".parse().unwrap();
+        let table = NeighbourTable {
+            neighbours: vec![
+                Neighbour {
+                    ip_addr,
+                    mac: mac(1),
+                    interface: "eth0".to_string(),
+                },
+                Neighbour {
+                    ip_addr,
+                    mac: mac(2),
+                    interface: "wlan0".to_string(),
+                },
+            ],
+            read_at: None,
+        };
+
+        assert_eq!(table.lookup(ip_addr, Some("wlan0")), Some(mac(2)));
+        assert_eq!(table.lookup(ip_addr, None), Some(mac(1)));
+        assert_eq!(table.lookup("fe80::2".parse().unwrap(), None), None);
+    }
+
+    #[test]
+    fn entries_added_after_a_read_are_found_once_it_is_stale() {
+        let ip_addr: IpAddr = "192.168.1.20".parse().unwrap();
+        let mac = MacAddr::new([0x02, 0, 0, 0, 0, 1]);
+        let updated = || NeighbourTable {
+            neighbours: vec![Neighbour {
+                ip_addr,
+                mac,
+                interface: "eth0".to_string(),
+            }],
+            read_at: None,
+        };
+        let start = Instant::now();
+        let mut table = NeighbourTable::default();
+
+        assert_eq!(
+            table.lookup_or_refresh_with(ip_addr, None, start, NeighbourTable::default),
+            None
+        );
+        assert_eq!(
+            table.lookup_or_refresh_with(ip_addr, None, start + REFRESH_INTERVAL / 2, || {
+                panic!("read again too soon")
+            }),
+            None
+        );
+        assert_eq!(
+            table.lookup_or_refresh_with(ip_addr, None, start + REFRESH_INTERVAL, updated),
+            Some(mac)
+        );
+    }
+}
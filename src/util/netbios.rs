@@ -0,0 +1,217 @@
+//! NetBIOS node status (NBSTAT) lookups.
+//!
+//! Windows machines and Samba servers answer these on UDP port 137 with the
+//! names they have registered, including their computer name and workgroup.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+use super::dns::query_id;
+
+pub(super) const NETBIOS_NAME_PORT: u16 = 137;
+const TYPE_NBSTAT: u16 = 0x0021;
+const CLASS_IN: u16 = 1;
+const HEADER_LEN: usize = 12;
+/// Length of an encoded NetBIOS name, including its length byte and terminator.
+const ENCODED_NAME_LEN: usize = 34;
+const NAME_ENTRY_LEN: usize = 18;
+/// Suffix of workstation and domain names.
+const SUFFIX_WORKSTATION: u8 = 0x00;
+const FLAG_GROUP: u16 = 0x8000;
+
+/// Names a host registered, as reported by a node status response.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct NodeStatus {
+    pub(crate) name: Option<String>,
+    pub(crate) workgroup: Option<String>,
+}
+
+impl NodeStatus {
+    /// `WORKGROUP\NAME`, or just the name when the workgroup is unknown.
+    pub(crate) fn hostname(&self) -> Option<String> {
+        let name = self.name.as_ref()?;
+        Some(match &self.workgroup {
+            Some(workgroup) => format!("{}\\{}", workgroup, name),
+            None => name.clone(),
+        })
+    }
+}
+
+/// Build a node status request for the wildcard name `*`.
+pub(crate) fn nbstat_query(id: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(HEADER_LEN + ENCODED_NAME_LEN + 4);
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+    packet.push(32);
+    let mut name = [0u8; 16];
+    name[0] = b'*';
+    for byte in name {
+        packet.push(b'A' + (byte >> 4));
+        packet.push(b'A' + (byte & 0x0f));
+    }
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_NBSTAT.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    packet
+}
+
+/// Parse the response to the node status request with `id`.
+pub(crate) fn parse_nbstat_response(id: u16, packet: &[u8]) -> Option<NodeStatus> {
+    if packet.get(..2)? != id.to_be_bytes() || packet.get(2)? & 0x80 == 0 {
+        return None;
+    }
+
+    // The answer repeats the encoded name, or points back at the question.
+    let mut offset = HEADER_LEN;
+    offset += match *packet.get(offset)? {
+        length if length & 0xc0 == 0xc0 => 2,
+        length => usize::from(length) + 2,
+    };
+    let record_type = u16::from_be_bytes([*packet.get(offset)?, *packet.get(offset + 1)?]);
+    if record_type != TYPE_NBSTAT {
+        return None;
+    }
+    offset += 10;
+
+    let count = usize::from(*packet.get(offset)?);
+    let entries = packet.get(offset + 1..offset + 1 + count * NAME_ENTRY_LEN)?;
+    let mut status = NodeStatus::default();
+    for entry in entries.chunks_exact(NAME_ENTRY_LEN) {
+        if entry[15] != SUFFIX_WORKSTATION {
+            continue;
+        }
+        let name = String::from_utf8_lossy(&entry[..15]).trim_end().to_string();
+        let group = u16::from_be_bytes([entry[16], entry[17]]) & FLAG_GROUP != 0;
+        let slot = if group {
+            &mut status.workgroup
+        } else {
+            &mut status.name
+        };
+        if slot.is_none() && !name.is_empty() {
+            *slot = Some(name);
+        }
+    }
+
+    Some(status)
+}
+
+/// Ask `ip_addr` for its NetBIOS names, waiting up to `timeout` for the answer.
+pub(crate) async fn node_status(ip_addr: IpAddr, timeout: Duration) -> Option<NodeStatus> {
+    match ip_addr {
+        IpAddr::V4(_) => {
+            query_node_status(SocketAddr::new(ip_addr, NETBIOS_NAME_PORT), timeout).await
+        }
+        // NetBIOS over TCP/IP only exists for IPv4.
+        IpAddr::V6(_) => None,
+    }
+}
+
+async fn query_node_status(address: SocketAddr, timeout: Duration) -> Option<NodeStatus> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await.ok()?;
+    socket.connect(address).await.ok()?;
+    let id = query_id(address.ip(), address);
+    socket.send(&nbstat_query(id)).await.ok()?;
+
+    let mut buffer = [0u8; 1500];
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        let received = tokio::time::timeout_at(deadline, socket.recv(&mut buffer))
+            .await
+            .ok()?
+            .ok()?;
+        if let Some(status) = parse_nbstat_response(id, &buffer[..received]) {
+            return Some(status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::net::UdpSocket;
+
+    use super::{NodeStatus, nbstat_query, parse_nbstat_response, query_node_status};
+
+    /// A node status response for a workstation in a workgroup, with its
+    /// file server and browser names.
+    fn nbstat_answer(query: &[u8]) -> Vec<u8> {
+        let mut response = query[..2].to_vec();
+        response.extend_from_slice(&[0x84, 0, 0, 0, 0, 1, 0, 0, 0, 0]);
+        response.extend_from_slice(&query[12..12 + 34]);
+        response.extend_from_slice(&[0, 0x21, 0, 1, 0, 0, 0, 0]);
+        let names: [(&str, u8, u16); 4] = [
+            ("DESKTOP-7F3K2", 0x00, 0x0400),
+            ("DESKTOP-7F3K2", 0x20, 0x0400),
+            ("WORKGROUP", 0x00, 0x8400),
+            ("WORKGROUP", 0x1e, 0x8400),
+        ];
+        let mut data = vec![names.len() as u8];
+        for (name, suffix, flags) in names {
+            data.extend_from_slice(format!("{:<15}", name).as_bytes());
+            data.push(suffix);
+            data.extend_from_slice(&flags.to_be_bytes());
+        }
+        data.extend_from_slice(&[0x3c, 0x52, 0x82, 0xaa, 0xbb, 0xcc]);
+        response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        response.extend_from_slice(&data);
+        response
+    }
+
+    #[test]
+    fn nbstat_queries_ask_for_the_wildcard_name() {
+        let query = nbstat_query(0x1234);
+
+        assert_eq!(query.len(), 50);
+        assert_eq!(&query[..4], &[0x12, 0x34, 0, 0]);
+        assert_eq!(&query[13..17], b"CKAA");
+        assert_eq!(&query[46..], &[0, 0x21, 0, 1]);
+    }
+
+    #[test]
+    fn nbstat_responses_yield_name_and_workgroup() {
+        let response = nbstat_answer(&nbstat_query(7));
+        let status = parse_nbstat_response(7, &response).unwrap();
+
+        assert_eq!(
+            status,
+            NodeStatus {
+                name: Some("DESKTOP-7F3K2".to_string()),
+                workgroup: Some("WORKGROUP".to_string()),
+            }
+        );
+        assert_eq!(
+            status.hostname(),
+            Some("WORKGROUP\\DESKTOP-7F3K2".to_string())
+        );
+        assert_eq!(parse_nbstat_response(8, &response), None);
+        assert_eq!(parse_nbstat_response(7, &response[..60]), None);
+    }
+
+    #[test]
+    fn node_status_queries_a_stub_responder() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let stub = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+                let address = stub.local_addr().unwrap();
+                tokio::spawn(async move {
+                    let mut buffer = [0u8; 512];
+                    let (received, peer) = stub.recv_from(&mut buffer).await.unwrap();
+                    let response = nbstat_answer(&buffer[..received]);
+                    stub.send_to(&response, peer).await.unwrap();
+                });
+
+                let status = query_node_status(address, Duration::from_secs(2)).await;
+
+                assert_eq!(
+                    status.and_then(|status| status.hostname()),
+                    Some("WORKGROUP\\DESKTOP-7F3K2".to_string())
+                );
+            });
+    }
+}
@@ -0,0 +1,174 @@
+//! Vendor names for hardware addresses.
+//!
+//! Reads the IEEE `oui.txt` registry and Wireshark's `manuf` file, both of
+//! which most Linux distributions install.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::{Error, MacAddr};
+
+/// Where distributions install an OUI database, in order of preference.
+pub(crate) const DEFAULT_OUI_PATHS: &[&str] = &[
+    "/usr/share/hwdata/oui.txt",
+    "/usr/share/ieee-data/oui.txt",
+    "/usr/share/misc/oui.txt",
+    "/usr/share/wireshark/manuf",
+];
+
+/// Vendor names keyed by address prefix.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct OuiDatabase {
+    /// Vendor names keyed by prefix length in bits and prefix value.
+    vendors: BTreeMap<(u8, u64), String>,
+    /// Prefix lengths present in `vendors`.
+    prefix_lens: BTreeSet<u8>,
+}
+
+impl OuiDatabase {
+    /// Load `path`, or the first default database that exists when it is unset.
+    pub(crate) fn load(path: Option<&Path>) -> Result<Self, Error> {
+        let path = match path {
+            Some(path) => path,
+            None => DEFAULT_OUI_PATHS
+                .iter()
+                .map(Path::new)
+                .find(|path| path.is_file())
+                .ok_or(Error::OuiDatabaseNotFound)?,
+        };
+
+        let contents = std::fs::read(path).map_err(|source| Error::Io {
+            context: format!("failed to read OUI database '{}'", path.display()),
+            source,
+        })?;
+        Ok(Self::parse(&String::from_utf8_lossy(&contents)))
+    }
+
+    /// Parse an IEEE `oui.txt` or Wireshark `manuf` file. Other lines are ignored.
+    pub(crate) fn parse(contents: &str) -> Self {
+        let mut database = Self::default();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // IEEE: `00-00-0C   (hex)\t\tCisco Systems, Inc`
+            // manuf: `00:00:0C\tCisco\tCisco Systems, Inc` or `00:1B:C5:00:00:00/36\t...`
+            let entry = match line.split_once("(hex)") {
+                Some((prefix, vendor)) => Some((prefix.trim(), vendor.trim())),
+                None => {
+                    let mut fields = line.split('\t').map(str::trim);
+                    let prefix = fields.next().unwrap_or_default();
+                    let short_name = fields.next().unwrap_or_default();
+                    let long_name = fields.next().filter(|name| !name.is_empty());
+                    Some((prefix, long_name.unwrap_or(short_name)))
+                }
+            };
+
+            if let Some((prefix, vendor)) = entry
+                && !vendor.is_empty()
+                && let Some((prefix_len, value)) = parse_prefix(prefix)
+            {
+                database.prefix_lens.insert(prefix_len);
+                database
+                    .vendors
+                    .entry((prefix_len, value))
+                    .or_insert_with(|| vendor.to_string());
+            }
+        }
+
+        database
+    }
+
+    /// Vendor of `mac`, using the longest matching prefix.
+    pub(crate) fn lookup(&self, mac: MacAddr) -> Option<&str> {
+        let [a, b, c, d, e, f] = mac.octets();
+        let address = u64::from_be_bytes([0, 0, a, b, c, d, e, f]);
+        self.prefix_lens.iter().rev().find_map(|prefix_len| {
+            self.vendors
+                .get(&(*prefix_len, address >> (48 - prefix_len)))
+                .map(String::as_str)
+        })
+    }
+}
+
+/// Parse `00-00-0C`, `00:00:0C` or `00:1B:C5:00:00:00/36` into a prefix
+/// length in bits and the prefix value.
+fn parse_prefix(prefix: &str) -> Option<(u8, u64)> {
+    let (digits, prefix_len) = match prefix.split_once('/') {
+        Some((digits, prefix_len)) => (digits, Some(prefix_len.parse::<u8>().ok()?)),
+        None => (prefix, None),
+    };
+    let digits: String = digits
+        .chars()
+        .filter(|c| !matches!(c, ':' | '-' | '.'))
+        .collect();
+    if !(6..=12).contains(&digits.len())
+        || !digits.len().is_multiple_of(2)
+        || !digits.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return None;
+    }
+
+    let bits = digits.len() as u8 * 4;
+    let prefix_len = prefix_len.unwrap_or(bits);
+    if prefix_len == 0 || prefix_len > bits {
+        return None;
+    }
+    let address = u64::from_str_radix(&digits, 16).ok()? << (48 - bits);
+    Some((prefix_len, address >> (48 - prefix_len)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OuiDatabase;
+    use crate::MacAddr;
+
+    #[test]
+    fn ieee_registries_map_prefixes_to_vendors() {
+        let database = OuiDatabase::parse(
+            "OUI/MA-L                                                    Organization
+company_id                                                  Organization
+                                                            Address
+
+B8-27-EB   (hex)\t\tRaspberry Pi Foundation
+B827EB     (base 16)\t\tRaspberry Pi Foundation
+\t\t\t\tMitchell Wood House
+",
+        );
+
+        assert_eq!(
+            database.lookup(MacAddr::new([0xb8, 0x27, 0xeb, 0x12, 0x34, 0x56])),
+            Some("Raspberry Pi Foundation")
+        );
+        assert_eq!(
+            database.lookup(MacAddr::new([0xb8, 0x27, 0xec, 0, 0, 0])),
+            None
+        );
+    }
+
+    #[test]
+    fn manuf_files_prefer_the_longest_prefix() {
+        let database = OuiDatabase::parse(
+            "# Wireshark manuf
+00:1B:C5\tIeeeRegi\tIEEE Registration Authority
+00:1B:C5:00:00:00/36\tConverg\tConverging Systems Inc.
+3C:52:82\tHP
+",
+        );
+
+        assert_eq!(
+            database.lookup(MacAddr::new([0x00, 0x1b, 0xc5, 0x00, 0x00, 0x42])),
+            Some("Converging Systems Inc.")
+        );
+        assert_eq!(
+            database.lookup(MacAddr::new([0x00, 0x1b, 0xc5, 0x00, 0x10, 0x00])),
+            Some("IEEE Registration Authority")
+        );
+        assert_eq!(
+            database.lookup(MacAddr::new([0x3c, 0x52, 0x82, 0xaa, 0xbb, 0xcc])),
+            Some("HP")
+        );
+    }
+}
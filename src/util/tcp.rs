@@ -0,0 +1,273 @@
+//! TCP connect probes, for networks that filter ICMP, and open port checks.
+//!
+//! A host is alive when any port answers, either by accepting the connection
+//! (SYN-ACK) or by refusing it (RST); a port is open only when it accepts.
+//! Connections are closed as soon as they are established; nothing is sent
+//! over them.
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::TcpSocket;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+use super::{Reply, Throttle, is_unreachable};
+
+/// Ports tried when none are given: SSH, HTTP, HTTPS and SMB.
+pub(crate) const DEFAULT_TCP_PORTS: &[u16] = &[22, 80, 443, 445];
+
+/// The 100 TCP ports most often found open, as ranked by nmap.
+pub const TOP_TCP_PORTS: &[u16; 100] = &[
+    7, 9, 13, 21, 22, 23, 25, 26, 37, 53, 79, 80, 81, 88, 106, 110, 111, 113, 119, 135, 139, 143,
+    144, 179, 199, 389, 427, 443, 444, 445, 465, 513, 514, 515, 543, 544, 548, 554, 587, 631, 646,
+    873, 990, 993, 995, 1025, 1026, 1027, 1028, 1029, 1110, 1433, 1720, 1723, 1755, 1900, 2000,
+    2001, 2049, 2121, 2717, 3000, 3128, 3306, 3389, 3986, 4899, 5000, 5009, 5051, 5060, 5101, 5190,
+    5357, 5432, 5631, 5666, 5800, 5900, 6000, 6001, 6646, 7070, 8000, 8008, 8009, 8080, 8081, 8443,
+    8888, 9100, 9999, 10000, 32768, 49152, 49153, 49154, 49155, 49156, 49157,
+];
+
+/// The ports [`tcp_ping`] tries for `ports`.
+pub(crate) fn tcp_ping_ports(ports: &[u16]) -> &[u16] {
+    if ports.is_empty() {
+        DEFAULT_TCP_PORTS
+    } else {
+        ports
+    }
+}
+
+/// Connect to every port of `ip_addr` at once and report the first answer.
+///
+/// With `list_open`, the other ports are still waited for until `timeout`,
+/// and the reply lists those that accepted in [`Reply::open_ports`].
+///
+/// Fails when no port answered and an attempt could not get out of this
+/// host, for example because it ran out of sockets.
+pub(crate) async fn tcp_ping(
+    ip_addr: &IpAddr,
+    source: Option<IpAddr>,
+    ports: &[u16],
+    timeout: Duration,
+    list_open: bool,
+) -> io::Result<Option<Reply>> {
+    let start = Instant::now();
+    let mut attempts = JoinSet::new();
+    for &port in tcp_ping_ports(ports) {
+        let address = SocketAddr::new(*ip_addr, port);
+        // `Some(accepted)` when the port answered.
+        attempts.spawn(async move {
+            let answer = match connect(address, source).await {
+                Ok(()) => Ok(Some(true)),
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => Ok(Some(false)),
+                Err(e) if is_unreachable(&e) => Ok(None),
+                Err(e) => Err(e),
+            };
+            (port, answer)
+        });
+    }
+
+    let deadline = start + timeout;
+    let mut reply: Option<Reply> = None;
+    let mut failure = None;
+    // Dropping the set aborts the attempts still waiting.
+    while let Ok(Some(attempt)) = tokio::time::timeout_at(deadline, attempts.join_next()).await {
+        match attempt {
+            Ok((port, Ok(Some(accepted)))) => {
+                let reply = reply.get_or_insert_with(|| Reply {
+                    rtt: Some(start.elapsed()),
+                    open_ports: Vec::new(),
+                });
+                if accepted {
+                    reply.open_ports.push(port);
+                }
+                if !list_open {
+                    break;
+                }
+            }
+            Ok((_, Err(e))) => failure = Some(e),
+            _ => {}
+        }
+    }
+
+    match reply {
+        Some(mut reply) => {
+            reply.open_ports.sort_unstable();
+            Ok(Some(reply))
+        }
+        None => failure.map_or(Ok(None), Err),
+    }
+}
+
+/// The `ports` of `host` that accept a connection within `timeout`, in
+/// ascending order. The port of `host` is ignored, but its IPv6 scope is kept.
+///
+/// Each attempt holds a permit of `throttle` while it waits, so checking
+/// many hosts stays within the scan's probe limits.
+pub(crate) async fn open_ports(
+    host: SocketAddr,
+    source: Option<IpAddr>,
+    ports: &[u16],
+    timeout: Duration,
+    throttle: &Arc<Throttle>,
+) -> Vec<u16> {
+    let mut attempts = JoinSet::new();
+    for &port in ports {
+        let throttle = throttle.clone();
+        attempts.spawn(async move {
+            let _permit = throttle.acquire().await?;
+            let mut address = host;
+            address.set_port(port);
+            match tokio::time::timeout(timeout, connect(address, source)).await {
+                Ok(Ok(())) => {
+                    throttle.succeeded();
+                    Some(port)
+                }
+                Ok(Err(e))
+                    if e.kind() != io::ErrorKind::ConnectionRefused && !is_unreachable(&e) =>
+                {
+                    throttle.failed();
+                    None
+                }
+                _ => {
+                    throttle.succeeded();
+                    None
+                }
+            }
+        });
+    }
+
+    let mut open = Vec::new();
+    while let Some(attempt) = attempts.join_next().await {
+        open.extend(attempt.ok().flatten());
+    }
+    open.sort_unstable();
+    open.dedup();
+    open
+}
+
+/// Connect to `address`, from `source` when given, and close the connection.
+async fn connect(address: SocketAddr, source: Option<IpAddr>) -> io::Result<()> {
+    let socket = match address {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    if let Some(source) = source {
+        socket.bind(SocketAddr::new(source, 0))?;
+    }
+
+    socket.connect(address).await.map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::net::TcpListener;
+
+    use super::{TOP_TCP_PORTS, open_ports, tcp_ping};
+    use crate::util::Throttle;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    #[test]
+    fn open_ports_answer() {
+        runtime().block_on(async {
+            let listener = TcpListener::bind((LOCALHOST, 0)).await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+
+            let reply = tcp_ping(&LOCALHOST, None, &[port], Duration::from_secs(2), false).await;
+
+            assert!(reply.unwrap().is_some_and(|reply| reply.rtt.is_some()));
+        });
+    }
+
+    #[test]
+    fn closed_ports_answer_with_a_reset() {
+        runtime().block_on(async {
+            let listener = TcpListener::bind((LOCALHOST, 0)).await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            drop(listener);
+
+            let reply = tcp_ping(&LOCALHOST, None, &[port], Duration::from_secs(2), false).await;
+
+            assert!(reply.unwrap().is_some());
+        });
+    }
+
+    #[test]
+    fn only_accepting_ports_are_open() {
+        runtime().block_on(async {
+            let first = TcpListener::bind((LOCALHOST, 0)).await.unwrap();
+            let second = TcpListener::bind((LOCALHOST, 0)).await.unwrap();
+            let closed = TcpListener::bind((LOCALHOST, 0)).await.unwrap();
+            let mut listening = [
+                first.local_addr().unwrap().port(),
+                second.local_addr().unwrap().port(),
+            ];
+            listening.sort_unstable();
+            let closed_port = closed.local_addr().unwrap().port();
+            drop(closed);
+            // One permit forces the attempts to take turns.
+            let throttle = Arc::new(Throttle::new(1, None, false));
+
+            let open = open_ports(
+                SocketAddr::new(LOCALHOST, 0),
+                None,
+                &[listening[1], closed_port, listening[0]],
+                Duration::from_secs(2),
+                &throttle,
+            )
+            .await;
+
+            assert_eq!(open, listening);
+            assert_eq!(throttle.available(), 1);
+        });
+    }
+
+    #[test]
+    fn pings_can_list_the_ports_that_accepted() {
+        runtime().block_on(async {
+            let first = TcpListener::bind((LOCALHOST, 0)).await.unwrap();
+            let second = TcpListener::bind((LOCALHOST, 0)).await.unwrap();
+            let closed = TcpListener::bind((LOCALHOST, 0)).await.unwrap();
+            let mut listening = [
+                first.local_addr().unwrap().port(),
+                second.local_addr().unwrap().port(),
+            ];
+            listening.sort_unstable();
+            let closed_port = closed.local_addr().unwrap().port();
+            drop(closed);
+
+            let reply = tcp_ping(
+                &LOCALHOST,
+                None,
+                &[closed_port, listening[1], listening[0]],
+                Duration::from_secs(2),
+                true,
+            )
+            .await;
+
+            assert_eq!(reply.unwrap().unwrap().open_ports, listening);
+        });
+    }
+
+    #[test]
+    fn top_ports_are_distinct() {
+        let mut ports = TOP_TCP_PORTS.to_vec();
+        ports.sort_unstable();
+        ports.dedup();
+
+        assert_eq!(ports.len(), 100);
+    }
+}
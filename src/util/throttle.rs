@@ -0,0 +1,243 @@
+//! Limits on how hard a scan probes: how many probes are in flight at once,
+//! how many start each second, and, in adaptive mode, backing off both when
+//! the local host or network shows signs of strain.
+//!
+//! Adaptive mode follows additive increase, multiplicative decrease: a
+//! failure halves the window of probes in flight, and every window's worth
+//! of probes that finish cleanly lets one more through, up to the limit. The
+//! rate scales with the window.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+/// Failures this soon after a backoff were probably already in flight, so
+/// they don't shrink the window again.
+const BACKOFF_COOLDOWN: Duration = Duration::from_secs(1);
+
+/// Shared by every probe of a scan.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    semaphore: Arc<Semaphore>,
+    limit: usize,
+    /// Time between probe starts at the full window, when rate limited.
+    interval: Option<Duration>,
+    adaptive: bool,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Probes allowed in flight, at most `limit`.
+    window: usize,
+    /// Permits to retire as they are released, after the window shrank below
+    /// the number in use.
+    debt: usize,
+    /// Earliest start of the next probe, when rate limited.
+    next_start: Instant,
+    /// Probes finished cleanly since the window last changed.
+    clean: usize,
+    backed_off_at: Option<Instant>,
+}
+
+/// Held for the duration of a probe.
+#[derive(Debug)]
+pub(crate) struct ThrottlePermit {
+    throttle: Arc<Throttle>,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Throttle {
+    /// Allow `limit` probes in flight and, when given, `rate` probe starts a
+    /// second. A limit or rate of 0 counts as 1.
+    pub(crate) fn new(limit: usize, rate: Option<u32>, adaptive: bool) -> Self {
+        let limit = limit.max(1);
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            limit,
+            interval: rate.map(|rate| Duration::from_secs(1) / rate.max(1)),
+            adaptive,
+            state: Mutex::new(State {
+                window: limit,
+                debt: 0,
+                next_start: Instant::now(),
+                clean: 0,
+                backed_off_at: None,
+            }),
+        }
+    }
+
+    /// Wait for room in the window and for the next start the rate allows.
+    ///
+    /// Returns `None` if the scan is shutting down.
+    pub(crate) async fn acquire(self: &Arc<Self>) -> Option<ThrottlePermit> {
+        let permit = self.semaphore.clone().acquire_owned().await.ok()?;
+        self.pace().await;
+        Some(ThrottlePermit {
+            throttle: self.clone(),
+            permit: Some(permit),
+        })
+    }
+
+    /// Wait for the next start the rate allows, for probes that are sent
+    /// in bulk rather than one per permit.
+    pub(crate) async fn pace(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let start = {
+            let mut state = self.state.lock().unwrap();
+            // Fewer probes in flight means proportionally fewer per second.
+            let interval = interval.mul_f64(self.limit as f64 / state.window as f64);
+            let start = state.next_start.max(Instant::now());
+            state.next_start = start + interval;
+            start
+        };
+        tokio::time::sleep_until(start).await;
+    }
+
+    /// Record a probe that finished without a local failure, whether or not
+    /// it got an answer.
+    pub(crate) fn succeeded(&self) {
+        if !self.adaptive {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.clean += 1;
+        if state.clean >= state.window && state.window < self.limit {
+            state.clean = 0;
+            state.window += 1;
+            if state.debt > 0 {
+                state.debt -= 1;
+            } else {
+                self.semaphore.add_permits(1);
+            }
+        }
+    }
+
+    /// Record a sign of strain: a probe that could not be sent, such as a
+    /// `ping` process that failed to start or a send that ran out of buffer
+    /// space, or a host that stopped answering most of its probes.
+    pub(crate) fn failed(&self) {
+        if !self.adaptive {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        if state
+            .backed_off_at
+            .is_some_and(|backed_off_at| now < backed_off_at + BACKOFF_COOLDOWN)
+        {
+            return;
+        }
+        state.backed_off_at = Some(now);
+        state.clean = 0;
+
+        let shrink = state.window - (state.window / 2).max(1);
+        state.window -= shrink;
+        let forgotten = self.semaphore.forget_permits(shrink);
+        state.debt += shrink - forgotten;
+    }
+
+    /// Probes currently allowed in flight.
+    #[cfg(test)]
+    fn window(&self) -> usize {
+        self.state.lock().unwrap().window
+    }
+
+    /// Permits not held by any probe.
+    #[cfg(test)]
+    pub(crate) fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+}
+
+impl Drop for ThrottlePermit {
+    fn drop(&mut self) {
+        let Some(permit) = self.permit.take() else {
+            return;
+        };
+        let mut state = self.throttle.state.lock().unwrap();
+        if state.debt > 0 {
+            state.debt -= 1;
+            permit.forget();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::Throttle;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn failures_halve_the_window_and_clean_probes_grow_it_back() {
+        runtime().block_on(async {
+            let throttle = Arc::new(Throttle::new(8, None, true));
+            let held: Vec<_> = acquire_many(&throttle, 6).await;
+
+            throttle.failed();
+            assert_eq!(throttle.window(), 4);
+            // Only two permits were free, so two more retire as probes finish.
+            assert_eq!(throttle.available(), 0);
+            drop(held);
+            assert_eq!(throttle.available(), 4);
+
+            // A second failure straight away is the same episode.
+            throttle.failed();
+            assert_eq!(throttle.window(), 4);
+
+            for _ in 0..4 {
+                throttle.succeeded();
+            }
+            assert_eq!(throttle.window(), 5);
+            assert_eq!(throttle.available(), 5);
+        });
+    }
+
+    #[test]
+    fn fixed_throttles_ignore_feedback() {
+        let throttle = Throttle::new(8, None, false);
+
+        throttle.failed();
+
+        assert_eq!(throttle.window(), 8);
+        assert_eq!(throttle.available(), 8);
+    }
+
+    #[test]
+    fn rates_space_out_probe_starts() {
+        runtime().block_on(async {
+            let throttle = Arc::new(Throttle::new(10, Some(50), false));
+            let start = Instant::now();
+
+            for _ in 0..6 {
+                drop(throttle.acquire().await);
+            }
+
+            // Five gaps of 20ms follow the first start.
+            assert!(start.elapsed() >= Duration::from_millis(100));
+        });
+    }
+
+    async fn acquire_many(throttle: &Arc<Throttle>, count: usize) -> Vec<super::ThrottlePermit> {
+        let mut permits = Vec::new();
+        for _ in 0..count {
+            permits.push(throttle.acquire().await.unwrap());
+        }
+        permits
+    }
+}
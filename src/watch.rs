@@ -0,0 +1,194 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ScanResult;
+
+/// A change in the set of responding hosts between watch rounds.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+#[allow(clippy::large_enum_variant)]
+pub enum WatchEvent {
+    /// A host answered for the first time, or again after being reported down.
+    Up(ScanResult),
+    /// A host missed the grace number of rounds in a row. Holds the last result seen.
+    Down(ScanResult),
+    /// A host's hostname or hardware address changed.
+    Changed {
+        previous: ScanResult,
+        current: ScanResult,
+    },
+}
+
+impl WatchEvent {
+    /// `up`, `down` or `changed`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WatchEvent::Up(_) => "up",
+            WatchEvent::Down(_) => "down",
+            WatchEvent::Changed { .. } => "changed",
+        }
+    }
+
+    /// The current state of the host, or its last known state when it is down.
+    pub fn result(&self) -> &ScanResult {
+        match self {
+            WatchEvent::Up(result) | WatchEvent::Down(result) => result,
+            WatchEvent::Changed { current, .. } => current,
+        }
+    }
+}
+
+/// Tracks hosts across repeated scans and reports what changed.
+///
+/// Feed every result of a round to [`Watcher::observe`], then call
+/// [`Watcher::end_round`] once the round's scan has finished.
+#[derive(Clone, Debug)]
+pub struct Watcher {
+    grace: usize,
+    hosts: BTreeMap<String, WatchedHost>,
+    seen: BTreeSet<String>,
+}
+
+#[derive(Clone, Debug)]
+struct WatchedHost {
+    result: ScanResult,
+    missed: usize,
+}
+
+impl Watcher {
+    /// Create a watcher that reports a host down after `grace` missed rounds.
+    ///
+    /// A grace of 0 is treated as 1.
+    pub fn new(grace: usize) -> Self {
+        Self {
+            grace: grace.max(1),
+            hosts: BTreeMap::new(),
+            seen: BTreeSet::new(),
+        }
+    }
+
+    /// Record a host that answered during the current round.
+    ///
+    /// A hostname or hardware address that could not be looked up this round
+    /// keeps its previous value rather than counting as a change.
+    pub fn observe(&mut self, mut result: ScanResult) -> Option<WatchEvent> {
+        let key = result.display_addr();
+        self.seen.insert(key.clone());
+
+        let Some(host) = self.hosts.get_mut(&key) else {
+            self.hosts.insert(
+                key,
+                WatchedHost {
+                    result: result.clone(),
+                    missed: 0,
+                },
+            );
+            return Some(WatchEvent::Up(result));
+        };
+
+        if result.hostname.is_none() {
+            result.hostname = host.result.hostname.clone();
+            result.hostnames = host.result.hostnames.clone();
+        }
+        if result.mac.is_none() {
+            result.mac = host.result.mac;
+            result.vendor = host.result.vendor.clone();
+        }
+
+        let previous = std::mem::replace(&mut host.result, result.clone());
+        host.missed = 0;
+        (previous.hostname != result.hostname || previous.mac != result.mac).then_some(
+            WatchEvent::Changed {
+                previous,
+                current: result,
+            },
+        )
+    }
+
+    /// Finish the current round, reporting hosts that have now missed too many rounds.
+    pub fn end_round(&mut self) -> Vec<WatchEvent> {
+        let seen = std::mem::take(&mut self.seen);
+        let mut events = Vec::new();
+        self.hosts.retain(|key, host| {
+            if seen.contains(key) {
+                return true;
+            }
+            host.missed += 1;
+            if host.missed < self.grace {
+                return true;
+            }
+            events.push(WatchEvent::Down(host.result.clone()));
+            false
+        });
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::{WatchEvent, Watcher};
+    use crate::{MacAddr, PingBackend, Probe, ScanResult};
+
+    fn host(last_octet: u8, hostname: Option<&str>) -> ScanResult {
+        let mut result = ScanResult::new(
+            IpAddr::V4(Ipv4Addr::new(192, 168, 1, last_octet)),
+            Probe::Sweep,
+            PingBackend::System,
+        );
+        result.hostname = hostname.map(str::to_string);
+        result
+    }
+
+    #[test]
+    fn hosts_go_down_only_after_the_grace_rounds() {
+        let mut watcher = Watcher::new(2);
+
+        assert_eq!(
+            watcher.observe(host(1, None)),
+            Some(WatchEvent::Up(host(1, None)))
+        );
+        assert_eq!(watcher.end_round(), vec![]);
+
+        assert_eq!(watcher.end_round(), vec![]);
+        assert_eq!(watcher.end_round(), vec![WatchEvent::Down(host(1, None))]);
+
+        assert_eq!(
+            watcher.observe(host(1, None)),
+            Some(WatchEvent::Up(host(1, None)))
+        );
+    }
+
+    #[test]
+    fn answering_again_resets_the_missed_rounds() {
+        let mut watcher = Watcher::new(2);
+        watcher.observe(host(1, None));
+        watcher.end_round();
+
+        watcher.end_round();
+        assert_eq!(watcher.observe(host(1, None)), None);
+        watcher.end_round();
+        assert_eq!(watcher.end_round(), vec![]);
+    }
+
+    #[test]
+    fn hostname_and_mac_changes_are_reported() {
+        let mut watcher = Watcher::new(1);
+        watcher.observe(host(1, Some("printer.local")));
+        watcher.end_round();
+
+        assert_eq!(watcher.observe(host(1, None)), None);
+        watcher.end_round();
+
+        let mut current = host(1, Some("printer.local"));
+        current.mac = Some(MacAddr::new([0x02, 0, 0, 0, 0, 1]));
+        assert_eq!(
+            watcher.observe(current.clone()),
+            Some(WatchEvent::Changed {
+                previous: host(1, Some("printer.local")),
+                current,
+            })
+        );
+    }
+}